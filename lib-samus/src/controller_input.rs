@@ -30,11 +30,6 @@ impl PartialEq for ControllerInput {
     fn eq(&self, other: &ControllerInput) -> bool {
         self.bits == other.bits
     }
-
-    #[inline]
-    fn ne(&self, other: &ControllerInput) -> bool {
-        self.bits != other.bits
-    }
 }
 
 impl Eq for ControllerInput {}
@@ -271,7 +266,7 @@ impl ControllerInput {
     #[inline]
     pub fn from_bits(bits: u16) -> Option<ControllerInput> {
         if (bits & !ControllerInput::all().bits()) == 0 {
-            Option::Some(ControllerInput { bits: bits })
+            Option::Some(ControllerInput { bits })
         } else { Option::None }
     }
 
@@ -279,7 +274,7 @@ impl ControllerInput {
     /// that do not correspond to flags.
     #[inline]
     pub fn from_bits_truncate(bits: u16) -> ControllerInput {
-        ControllerInput { bits: bits } & ControllerInput::all()
+        ControllerInput { bits } & ControllerInput::all()
     }
 
     /// Returns `true` if no flags are currently stored.
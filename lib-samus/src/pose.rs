@@ -3,40 +3,136 @@ pub use controller_input::ControllerInput;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Transition {
-    pub input: ControllerInput,
+    /// Buttons that have to go down this frame
+    pub pressed: ControllerInput,
+    /// Buttons that have to be down, whenever they were pressed
+    pub held: ControllerInput,
     pub to_pose: u8,
 }
 
+impl Transition {
+    /// A transition with neither condition only fires once everything is let go
+    pub fn matches(&self, newly_pressed: ControllerInput, current: ControllerInput) -> bool {
+        if self.pressed.is_empty() && self.held.is_empty() {
+            current.is_empty()
+        } else {
+            newly_pressed.contains(self.pressed) && current.contains(self.held)
+        }
+    }
+}
+
 #[cfg(feature="codegen")]
 impl ToTokens for Transition {
     fn to_tokens(&self, tokens: &mut Tokens) {
-        let input_bits = self.input.bits();
+        let pressed_bits = self.pressed.bits();
+        let held_bits = self.held.bits();
         let to_pose = self.to_pose;
         tokens.append_all(quote!{
             Transition {
-                input: ControllerInput { bits: #input_bits },
+                pressed: ControllerInput { bits: #pressed_bits },
+                held: ControllerInput { bits: #held_bits },
                 to_pose: #to_pose,
             }
         });
     }
 }
 
+/// Which way Samus faces in a pose
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Left,
+    Right,
+}
+
+#[cfg(feature="codegen")]
+impl ToTokens for Direction {
+    fn to_tokens(&self, tokens: &mut Tokens) {
+        tokens.append_all(match *self {
+            Direction::Forward => quote!(Direction::Forward),
+            Direction::Left => quote!(Direction::Left),
+            Direction::Right => quote!(Direction::Right),
+        });
+    }
+}
+
+/// Equipment bits, as stored in Samus's equipped items
+pub mod items {
+    pub const VARIA: u16 = 0x0001;
+    pub const SPRING_BALL: u16 = 0x0002;
+    pub const MORPH_BALL: u16 = 0x0004;
+    pub const SCREW_ATTACK: u16 = 0x0008;
+    pub const GRAVITY: u16 = 0x0020;
+    pub const HI_JUMP: u16 = 0x0100;
+    pub const SPACE_JUMP: u16 = 0x0200;
+    pub const BOMBS: u16 = 0x1000;
+    pub const SPEED_BOOSTER: u16 = 0x2000;
+    pub const GRAPPLE: u16 = 0x4000;
+    pub const XRAY: u16 = 0x8000;
+}
+
+/// Game state that some terminators branch on
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Conditions {
+    pub items: u16,
+    pub moving_up: bool,
+}
+
+impl Conditions {
+    #[inline]
+    fn has(&self, items: u16) -> bool {
+        self.items & items == items
+    }
+}
+
+/// The instruction that ends a pose's frame delay list (0xF0 to 0xFF)
 #[derive(Copy, Clone, Debug)]
 pub enum Terminator {
+    /// FF: go back to the first frame
     Loop,
+    /// FE nn: go back `nn` frames
     Backtrack(u8),
+    /// FD pp: transition to pose `pp`
     TransitionTo(u8),
+    /// FC iiii pp qq: transition to `with` if the items are equipped, else `without`
+    TransitionOnItem { items: u16, with: u8, without: u8 },
+    /// FB: wall jump. The game picks the spin that fits the equipment and
+    /// keeps looping it.
+    WallJump,
+    /// FA pp qq: transition depending on whether Samus is moving up or down
+    TransitionOnDirection { up: u8, down: u8 },
+    /// F9 iiii aa bb cc dd: both of the above at once
+    TransitionOnItemAndDirection { items: u16, with_up: u8, with_down: u8, without_up: u8, without_down: u8 },
+    /// F8 pp: transition to pose `pp`, and keep jumping if jump is still held
+    AutoJump(u8),
+    /// F6: loop, panting if Samus is low on health
+    HeavyBreathing,
+    /// F0: hold the last frame
     Stop,
+    /// F1-F5, F7: not used by any pose, treated as a loop
+    Unused(u8),
 }
 
 #[cfg(feature="codegen")]
 impl ToTokens for Terminator {
     fn to_tokens(&self, tokens: &mut Tokens) {
-        let ts = match self {
-            &Terminator::Loop => quote!(Terminator::Loop),
-            &Terminator::Backtrack(ref b) => quote!(Terminator::Backtrack(#b)),
-            &Terminator::TransitionTo(ref t) => quote!(Terminator::TransitionTo(#t)),
-            &Terminator::Stop => quote!(Terminator::Stop),
+        let ts = match *self {
+            Terminator::Loop => quote!(Terminator::Loop),
+            Terminator::Backtrack(ref b) => quote!(Terminator::Backtrack(#b)),
+            Terminator::TransitionTo(ref t) => quote!(Terminator::TransitionTo(#t)),
+            Terminator::TransitionOnItem { items, with, without } =>
+                quote!(Terminator::TransitionOnItem { items: #items, with: #with, without: #without }),
+            Terminator::WallJump => quote!(Terminator::WallJump),
+            Terminator::TransitionOnDirection { up, down } =>
+                quote!(Terminator::TransitionOnDirection { up: #up, down: #down }),
+            Terminator::TransitionOnItemAndDirection { items, with_up, with_down, without_up, without_down } =>
+                quote!(Terminator::TransitionOnItemAndDirection {
+                    items: #items, with_up: #with_up, with_down: #with_down, without_up: #without_up, without_down: #without_down
+                }),
+            Terminator::AutoJump(ref t) => quote!(Terminator::AutoJump(#t)),
+            Terminator::HeavyBreathing => quote!(Terminator::HeavyBreathing),
+            Terminator::Stop => quote!(Terminator::Stop),
+            Terminator::Unused(ref op) => quote!(Terminator::Unused(#op)),
         };
         tokens.append_all(ts);
     }
@@ -79,6 +175,9 @@ impl<'a> ToTokens for Frame<'a> {
 pub struct Pose<'a> {
     pub name: &'a str,
     pub id: usize,
+    pub direction: Direction,
+    /// Left, top, right, bottom, inclusive, relative to Samus's center
+    pub hitbox: (i16, i16, i16, i16),
     pub terminator: Terminator,
     pub durations: &'a [u8],
     pub frames: &'a [Frame<'a>],
@@ -88,10 +187,13 @@ pub struct Pose<'a> {
 }
 
 impl<'a> Pose<'a> {
-    pub fn next(&mut self) -> Next<'a> {
+    pub fn next(&mut self, conditions: &Conditions) -> Next<'a> {
         let next = if self.cursor >= self.length {
             match self.terminator {
-                Terminator::Loop => {
+                Terminator::Loop |
+                Terminator::WallJump |
+                Terminator::HeavyBreathing |
+                Terminator::Unused(_) => {
                     self.cursor = 0;
                     Next::Frame(&self.frames[0], self.durations[0])
                 },
@@ -100,12 +202,27 @@ impl<'a> Pose<'a> {
                     Next::Frame(&self.frames[self.cursor], self.durations[self.cursor])
                 },
                 Terminator::Stop => Next::Frame(&self.frames[self.cursor-1], self.durations[self.cursor-1]), //optimization?
-                Terminator::TransitionTo(pose) => Next::NewPose(pose),
+                Terminator::TransitionTo(pose) |
+                Terminator::AutoJump(pose) => Next::NewPose(pose),
+                Terminator::TransitionOnItem { items, with, without } => {
+                    Next::NewPose(if conditions.has(items) { with } else { without })
+                },
+                Terminator::TransitionOnDirection { up, down } => {
+                    Next::NewPose(if conditions.moving_up { up } else { down })
+                },
+                Terminator::TransitionOnItemAndDirection { items, with_up, with_down, without_up, without_down } => {
+                    Next::NewPose(match (conditions.has(items), conditions.moving_up) {
+                        (true, true) => with_up,
+                        (true, false) => with_down,
+                        (false, true) => without_up,
+                        (false, false) => without_down,
+                    })
+                },
             }
         } else {
             Next::Frame(&self.frames[self.cursor], self.durations[self.cursor])
         };
-        self.cursor = self.cursor + 1;
+        self.cursor += 1;
         next
     }
 }
@@ -1,9 +1,13 @@
-use pose::{Pose, Frame, Next};
+use pose::{Conditions, Direction, Pose, Frame, Next};
 use controller_input::ControllerInput;
 
 pub struct StateMachine<'a> {
     current: Pose<'a>,
     input: ControllerInput,
+    // What was held when the last tick ended. Buttons down now but not then
+    // count as newly pressed, however many times `input` gets called.
+    previous: ControllerInput,
+    conditions: Conditions,
     lookup: fn(usize) -> Option<&'a Pose<'a>>,
 }
 
@@ -12,12 +16,16 @@ impl<'a> StateMachine<'a> {
         StateMachine {
             current: (lookup)(initial).expect("Passed a nonexistant initial pose state to StateMachine::new").clone(),
             input: ControllerInput::empty(),
+            previous: ControllerInput::empty(),
+            conditions: Conditions::default(),
             lookup,
         }
     }
 
+    // Not an Iterator, it never runs out
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> (&'a Frame<'a>, u8) {
-        match self.current.next() {
+        match self.current.next(&self.conditions) {
             Next::Frame(frame, duration) => (frame, duration),
             Next::NewPose(n) => {
                 let updated = self.goto(n as usize);
@@ -31,9 +39,10 @@ impl<'a> StateMachine<'a> {
         }
     }
 
-    pub fn input(&mut self, pressed: ControllerInput) -> bool {
-        self.input = pressed;
-        if let Some(transition) = self.current.transitions.iter().find(|t| t.input == pressed) {
+    pub fn input(&mut self, current: ControllerInput) -> bool {
+        let newly_pressed = current - self.previous;
+        self.input = current;
+        if let Some(transition) = self.current.transitions.iter().find(|t| t.matches(newly_pressed, current)) {
             if transition.to_pose != self.current.id as u8 {
                 // FIXME: may want to extend an exception to
                 // space-jumping into the same animation
@@ -43,6 +52,13 @@ impl<'a> StateMachine<'a> {
         false
     }
 
+    /// Ends a frame of the game: whatever's held from now on is no longer
+    /// newly pressed. Call it once per frame, after that frame's input.
+    #[inline]
+    pub fn tick(&mut self) {
+        self.previous = self.input;
+    }
+
     pub fn bonk(&mut self) -> bool {
         // let next_pose = match self.current.id {
         //     0x09 | 0x0B => 0x89, // running right
@@ -113,6 +129,18 @@ impl<'a> StateMachine<'a> {
         }
     }
 
+    /// Equipped items, for poses whose animations end differently
+    /// depending on them
+    #[inline]
+    pub fn set_items(&mut self, items: u16) {
+        self.conditions.items = items;
+    }
+
+    #[inline]
+    pub fn set_moving_up(&mut self, moving_up: bool) {
+        self.conditions.moving_up = moving_up;
+    }
+
     #[inline]
     pub fn pose_name(&self) -> &'a str {
         self.current.name
@@ -123,8 +151,72 @@ impl<'a> StateMachine<'a> {
         self.current.id
     }
 
+    #[inline]
+    pub fn direction(&self) -> Direction {
+        self.current.direction
+    }
+
+    #[inline]
+    pub fn hitbox(&self) -> (i16, i16, i16, i16) {
+        self.current.hitbox
+    }
+
     #[inline]
     pub fn current_input(&self) -> ControllerInput {
         self.input
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pose::{Direction, Frame, Pose, Terminator, Transition};
+    use controller_input::ControllerInput;
+    use super::StateMachine;
+
+    static FRAMES: [Frame<'static>; 1] = [Frame { buffer: &[], width: 0, height: 0, zero_x: 0, zero_y: 0 }];
+
+    // Standing goes to 1 when right and jump go down together, or to 2 when
+    // they're held however they got there
+    static STANDING: [Transition; 2] = [
+        Transition { pressed: ControllerInput { bits: 0x0180 }, held: ControllerInput { bits: 0 }, to_pose: 1 },
+        Transition { pressed: ControllerInput { bits: 0 }, held: ControllerInput { bits: 0x0180 }, to_pose: 2 },
+    ];
+
+    static POSES: [Pose<'static>; 3] = [
+        Pose { name: "standing", id: 0, direction: Direction::Right, hitbox: (0, 0, 0, 0), terminator: Terminator::Loop, durations: &[1], frames: &FRAMES, transitions: &STANDING, length: 1, cursor: 0 },
+        Pose { name: "jumping", id: 1, direction: Direction::Right, hitbox: (0, 0, 0, 0), terminator: Terminator::Loop, durations: &[1], frames: &FRAMES, transitions: &[], length: 1, cursor: 0 },
+        Pose { name: "held", id: 2, direction: Direction::Right, hitbox: (0, 0, 0, 0), terminator: Terminator::Loop, durations: &[1], frames: &FRAMES, transitions: &[], length: 1, cursor: 0 },
+    ];
+
+    fn lookup(n: usize) -> Option<&'static Pose<'static>> {
+        POSES.get(n)
+    }
+
+    #[test]
+    fn pressed_within_a_tick_stays_pressed() {
+        // Two key events in one frame, ORed together the way the viewers do
+        let mut samus = StateMachine::new(0, lookup);
+        assert!(!samus.input(ControllerInput::Right));
+        assert!(samus.input(ControllerInput::Right | ControllerInput::Jump));
+        assert_eq!(samus.pose_state(), 1);
+    }
+
+    #[test]
+    fn held_buttons_arent_pressed_again() {
+        let mut samus = StateMachine::new(0, lookup);
+        samus.input(ControllerInput::Jump);
+        samus.tick();
+        // Jump went down last frame, so only the held transition can fire
+        assert!(samus.input(ControllerInput::Right | ControllerInput::Jump));
+        assert_eq!(samus.pose_state(), 2);
+    }
+
+    #[test]
+    fn transitions_match_supersets() {
+        let t = STANDING[0];
+        let everything = ControllerInput::Right | ControllerInput::Jump | ControllerInput::Shoot;
+        assert!(t.matches(everything, everything));
+        assert!(!t.matches(ControllerInput::Jump | ControllerInput::Shoot, everything));
+        assert!(!STANDING[1].matches(ControllerInput::empty(), ControllerInput::Jump));
+    }
+}
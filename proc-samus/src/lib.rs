@@ -4,7 +4,6 @@ extern crate proc_macro;
 extern crate proc_macro2;
 #[macro_use] extern crate syn;
 #[macro_use] extern crate quote;
-extern crate byteorder;
 
 extern crate sm;
 extern crate lib_samus;
@@ -18,13 +17,12 @@ use syn::{Ident, Expr};
 use syn::punctuated::Punctuated;
 use syn::synom::Synom;
 use quote::{Tokens, ToTokens};
-use byteorder::{ByteOrder, LittleEndian};
 
 use sm::{snes, samus, frame_map, util};
-use snes::{Rom, PcAddress};
-use lib_samus::pose::{ControllerInput, Transition, Terminator};
+use snes::Rom;
+use lib_samus::pose::{ControllerInput, Direction, Transition, Terminator};
 use frame_map::FrameMap;
-use util::{zip3, bgr555_rgb888};
+use util::{zip3, ColorConversion};
 
 const ROM_DATA: &'static [u8] = include_bytes!("../../data/Super Metroid (Japan, USA) (En,Ja).sfc");
 const ROM: Rom = Rom(ROM_DATA);
@@ -45,22 +43,28 @@ fn parse_pose_state(state: syn::Expr) -> usize {
     }
 }
 
-fn samus_pose_struct_tokens(name: Ident, state: usize, default_state: usize, v_offset: u8) -> Tokens {
+fn samus_pose_struct_tokens(name: Ident, state: usize, default_state: usize, cannon: bool) -> Tokens {
     let name_str = name.into_tokens().to_string();
+    let definition = samus::pose_definition(&ROM, state);
+    let v_offset = definition.v_offset();
     let sequence = samus::lookup_frame_sequence(&ROM, state);
     let durations = sequence.0;
     let sequence_terminator = sequence.1;
     let transitions = sequence.2;
     let sequence_len = durations.len();
 
-    let tile_maps = samus::tilemaps(&ROM, state, durations.len());
-    let tile_sets = samus::graphics(&ROM, state, durations.len());
+    let mut tile_maps = samus::tilemaps(&ROM, state, durations.len());
+    let mut tile_sets = samus::graphics(&ROM, state, durations.len());
+    if cannon {
+        samus::add_cannon(&ROM, state, &mut tile_maps, &mut tile_sets);
+    }
     let frames: Vec<_> = zip3(tile_maps, &tile_sets, durations)
         .map(|(tm, ts, ds)| FrameMap::composite(&tm, &ts, *ds as u16, v_offset)).collect();
 
     // Sure wish we could use lib-samus with the codegen feature here...
     let borrow_frames: Vec<_> = frames.into_iter().map(|f| {
-        let buffer = f.buffer;
+        // lib-samus frames only ever get drawn with the one palette
+        let buffer: Vec<u8> = f.buffer.iter().map(|px| px & 0x0F).collect();
         let width = f.width;
         let height = f.height;
         let zero_x = f.zero_x;
@@ -79,17 +83,20 @@ fn samus_pose_struct_tokens(name: Ident, state: usize, default_state: usize, v_o
     let mut transitions = transitions.to_vec();
     if default_state != 0xFF && default_state != state {
         transitions.push(Transition {
-            input: ControllerInput::empty(),
+            pressed: ControllerInput::empty(),
+            held: ControllerInput::empty(),
             to_pose: default_state as u8,
         });
     }
 
     let transitions: Vec<_> = transitions.into_iter().map(|t| {
-        let input_bits = t.input.bits();
+        let pressed_bits = t.pressed.bits();
+        let held_bits = t.held.bits();
         let to_pose = t.to_pose;
         quote!{
             Transition {
-                input: ControllerInput { bits: #input_bits },
+                pressed: ControllerInput { bits: #pressed_bits },
+                held: ControllerInput { bits: #held_bits },
                 to_pose: #to_pose,
             }
         }
@@ -99,13 +106,34 @@ fn samus_pose_struct_tokens(name: Ident, state: usize, default_state: usize, v_o
         Terminator::Loop => quote!(Terminator::Loop),
         Terminator::Backtrack(ref b) => quote!(Terminator::Backtrack(#b)),
         Terminator::TransitionTo(ref t) => quote!(Terminator::TransitionTo(#t)),
+        Terminator::TransitionOnItem { items, with, without } =>
+            quote!(Terminator::TransitionOnItem { items: #items, with: #with, without: #without }),
+        Terminator::WallJump => quote!(Terminator::WallJump),
+        Terminator::TransitionOnDirection { up, down } =>
+            quote!(Terminator::TransitionOnDirection { up: #up, down: #down }),
+        Terminator::TransitionOnItemAndDirection { items, with_up, with_down, without_up, without_down } =>
+            quote!(Terminator::TransitionOnItemAndDirection {
+                items: #items, with_up: #with_up, with_down: #with_down, without_up: #without_up, without_down: #without_down
+            }),
+        Terminator::AutoJump(ref t) => quote!(Terminator::AutoJump(#t)),
+        Terminator::HeavyBreathing => quote!(Terminator::HeavyBreathing),
         Terminator::Stop => quote!(Terminator::Stop),
+        Terminator::Unused(ref op) => quote!(Terminator::Unused(#op)),
+    };
+
+    let direction = match definition.direction {
+        Direction::Forward => quote!(Direction::Forward),
+        Direction::Left => quote!(Direction::Left),
+        Direction::Right => quote!(Direction::Right),
     };
+    let (left, top, right, bottom) = definition.hitbox();
 
     quote!{
         Pose {
             name: #name_str,
             id: #state,
+            direction: #direction,
+            hitbox: (#left, #top, #right, #bottom),
             terminator: #sequence_terminator,
             durations: &[#(#durations),*],
             transitions: &[#(#transitions),*],
@@ -116,28 +144,39 @@ fn samus_pose_struct_tokens(name: Ident, state: usize, default_state: usize, v_o
     }
 }
 
+// `samus_poses!([0x01, 0x02])`, or `samus_poses!([0x01, 0x02], cannon)` to
+// draw the arm cannon too
 struct Chosen {
     ids: HashSet<Expr>,
+    flag: Option<Ident>,
 }
 
 impl Synom for Chosen {
-    named!(parse -> Self, map!(
-        brackets!(Punctuated::<Expr, Token![,]>::parse_terminated),
-        |(_parens, vars)| Chosen {
-            ids: vars.into_iter().collect(),
-        }
+    named!(parse -> Self, do_parse!(
+        ids: brackets!(Punctuated::<Expr, Token![,]>::parse_terminated) >>
+        flag: option!(do_parse!(punct!(,) >> flag: syn!(Ident) >> (flag))) >>
+        (Chosen {
+            ids: ids.1.into_iter().collect(),
+            flag: flag,
+        })
     ));
 }
 
-fn parse_chosen_poses(input: TokenStream) -> Vec<(Ident, usize, usize, u8)> {
-    let Chosen { ids } = syn::parse(input).expect("eep, hi there");
+fn parse_chosen_poses(input: TokenStream) -> (Vec<(Ident, usize, usize)>, bool) {
+    let Chosen { ids, flag } = syn::parse(input).expect("eep, hi there");
+    let cannon = match flag {
+        Some(ref flag) if flag.as_ref() == "cannon" => true,
+        Some(ref flag) => panic!("proc-samus::samus_poses: unknown option `{}`, only `cannon` is allowed", flag.as_ref()),
+        None => false,
+    };
+    let all = poses_list::all(&ROM);
 
     let chosen: HashSet<_> = {
         let chosen: Vec<_> = ids.into_iter()
             .map(|state| parse_pose_state(state))
             .collect();
-        let defaults: Vec<_> = poses_list::ALL.iter()
-            .filter_map(|&(state, _, default_state, _)| {
+        let defaults: Vec<_> = all.iter()
+            .filter_map(|&(state, _, default_state)| {
                 if chosen.contains(&state) {
                     Some(default_state)
                 } else {
@@ -149,30 +188,31 @@ fn parse_chosen_poses(input: TokenStream) -> Vec<(Ident, usize, usize, u8)> {
         chosen.into_iter().chain(defaults.into_iter()).collect()
     };
 
-    poses_list::ALL.iter()
-        .filter_map(|&(state, name_str, default_state, v_offset)| {
+    let poses = all.iter()
+        .filter_map(|&(state, ref name_str, default_state)| {
             if chosen.is_empty() || chosen.contains(&state) {
-                let name = Ident::from(name_str);
-                Some((name, state, default_state, v_offset))
+                let name = Ident::from(name_str.as_str());
+                Some((name, state, default_state))
             } else {
                 None
             }
         })
-        .collect()
+        .collect();
+    (poses, cannon)
 }
 
 const NUM_POSES: usize = 256;
 
 #[proc_macro]
 pub fn samus_poses(input: TokenStream) -> TokenStream {
-    let poses = parse_chosen_poses(input);
+    let (poses, cannon) = parse_chosen_poses(input);
     let poses_tokens: Vec<_> = poses.iter()
-        .map(|&(name, state, default_state, v_offset)| samus_pose_struct_tokens(name, state, default_state, v_offset))
+        .map(|&(name, state, default_state)| samus_pose_struct_tokens(name, state, default_state, cannon))
         .collect();
 
     let len = poses_tokens.len();
     let mut arr = vec![255u8; NUM_POSES];
-    for (n, &(_name, state, _default_state, _v_offset)) in poses.iter().enumerate() {
+    for (n, &(_name, state, _default_state)) in poses.iter().enumerate() {
         arr[state] = n as u8;
     }
 
@@ -196,20 +236,30 @@ pub fn samus_poses(input: TokenStream) -> TokenStream {
 
 #[proc_macro]
 pub fn samus_palettes(_input: TokenStream) -> TokenStream {
-    let addr = 0xD9400;
-
-    let p = PcAddress(addr);
-    let palette = ROM.read(p, 32)
-        .chunks(2)
-        .map(LittleEndian::read_u16)
-        .map(|c| bgr555_rgb888(&c));
+    // Baked the same way sprites convert colors unless told otherwise
+    let conversion = ColorConversion::default();
+    let palettes: Vec<_> = samus::palettes(&ROM).into_iter()
+        .map(|(name, colors)| {
+            let colors: Vec<_> = colors.iter()
+                .map(|c| conversion.rgb888(c))
+                .map(|(r, g, b)| quote!{(#r, #g, #b)})
+                .collect();
+            (name, colors)
+        })
+        .collect();
 
-    let palette_tokens: Vec<_> = palette.map(|(r, g, b)| quote!{(#r, #g, #b)}).collect();
+    // The power suit comes first
+    let palette_tokens = &palettes[0].1;
     let palette_len = palette_tokens.len();
+    let palettes_len = palettes.len();
+    let named_palettes: Vec<_> = palettes.iter()
+        .map(|&(ref name, ref colors)| quote!{(#name, [#(#colors),*])})
+        .collect();
 
     TokenStream::from(quote!{
         mod palette {
             pub static PALETTE: [(u8, u8, u8); #palette_len] = [#(#palette_tokens),*];
+            pub static PALETTES: [(&'static str, [(u8, u8, u8); #palette_len]); #palettes_len] = [#(#named_palettes),*];
         }
     })
 }
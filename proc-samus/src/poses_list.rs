@@ -1,196 +1,9 @@
-pub static ALL: &[(usize, &'static str, usize, u8)] = &[
-    (0x00, "elevator_pose", 0xFF, 8),
-    (0x01, "facing_right_normal", 0xFF, 6),
-    (0x02, "facing_left_normal", 0xFF, 6),
-    (0x03, "facing_right_aiming_up", 0x01, 6),
-    (0x04, "facing_left_aiming_up", 0x02, 6),
-    (0x05, "facing_right_aiming_upright", 0x01, 6),
-    (0x06, "facing_left_aiming_upleft", 0x02, 6),
-    (0x07, "facing_right_aiming_downright", 0x01, 6),
-    (0x08, "facing_left_aiming_downleft", 0x02, 6),
-    (0x09, "moving_right_not_aiming", 0x01, 6),
-    (0x0A, "moving_left_not_aiming", 0x02, 6),
-    (0x0B, "moving_right_gun_extended_not_aiming", 0x01, 6),
-    (0x0C, "moving_left_gun_extended_not_aiming", 0x02, 6),
-    (0x0F, "moving_right_aiming_upright", 0x01, 6),
-    (0x10, "moving_left_aiming_upleft", 0x02, 6),
-    (0x11, "moving_right_aiming_downright", 0x01, 6),
-    (0x12, "moving_left_aiming_downleft", 0x02, 6),
-    (0x13, "jump_facing_right_gun_extended_not_aiming_or_moving", 0xFF, 8),
-    (0x14, "jump_facing_left_gun_extended_not_aiming_or_moving", 0xFF, 8),
-    (0x15, "jump_facing_right_aiming_up", 0x51, 8),
-    (0x16, "jump_facing_left_aiming_up", 0x52, 8),
-    (0x17, "jump_facing_right_aiming_down", 0xFF, 6),
-    (0x18, "jump_facing_left_aiming_down", 0xFF, 6),
-    (0x19, "spin_jump_right", 0xFF, 0),
-    (0x1A, "spin_jump_left", 0xFF, 0),
-    (0x1B, "space_jump_right", 0xFF, 0),
-    (0x1C, "space_jump_left", 0xFF, 0),
-    (0x1D, "morphball_facing_right", 0xFF, 0),
-    (0x1E, "morphball_moving_right", 0xFF, 0),
-    (0x1F, "morphball_moving_left", 0x41, 0),
-    (0x25, "standing_turn_right_to_left", 0xFF, 6),
-    (0x26, "standing_turn_left_to_right", 0xFF, 6),
-    (0x27, "crouching_right", 0x27, 0),
-    (0x28, "crouching_left", 0x28, 0),
-    (0x29, "falling_right_normal", 0xFF, 8),
-    (0x2A, "falling_left_normal", 0xFF, 8),
-    (0x2B, "falling_right_aiming_up", 0x29, 8),
-    (0x2C, "falling_left_aiming_up", 0x2A, 8),
-    (0x2D, "falling_right_aiming_down", 0xFF, 6),
-    (0x2E, "falling_left_aiming_down", 0xFF, 6),
-    (0x2F, "jumping_turn_right_to_left", 0xFF, 8),
-    (0x30, "jumping_turn_left_to_right", 0xFF, 8),
-    (0x31, "midair_morphball_facing_right", 0xFF, 0),
-    (0x32, "midair_morphball_facing_left", 0xFF, 0),
-    (0x35, "crouch_transition_facing_right", 0xFF, 0),
-    (0x36, "crouch_transition_facing_left", 0xFF, 0),
-    (0x37, "morphing_facing_right", 0xFF, 0),
-    (0x38, "morphing_facing_left", 0xFF, 0),
-    (0x3B, "standing_from_crouch_facing_right", 0xFF, 6),
-    (0x3C, "standing_from_crouch_facing_left", 0xFF, 6),
-    (0x3D, "demorph_facing_right", 0xFF, 0),
-    (0x3E, "demorph_facing_left", 0xFF, 0),
-    (0x41, "morphball_facing_left", 0xFF, 0),
-    (0x43, "crouching_turn_right_to_left", 0xFF, 0),
-    (0x44, "crouching_turn_left_to_right", 0xFF, 0),
-    (0x49, "moonwalk_right_facing_left", 0x02, 6),
-    (0x4A, "moonwalk_left_facing_right", 0x01, 6),
-    (0x4B, "jump_transition_stand_crouch_facing_right", 0xFF, 3),
-    (0x4C, "jump_transition_stand_crouch_facing_left", 0xFF, 3),
-    (0x4D, "jump_facing_right_gun_not_extended_not_aiming_or_moving", 0xFF, 8),
-    (0x4E, "jump_facing_left_gun_not_extended_not_aiming_or_moving", 0xFF, 8),
-    (0x4F, "hurt_rollback_right_facing_left", 0x4E, 8),
-    (0x50, "hurt_rollback_left_facing_right", 0x4D, 8),
-    (0x51, "jump_forward_facing_right_gun_extended", 0xFF, 8),
-    (0x52, "jump_forward_facing_left_gun_extended", 0xFF, 8),
-    (0x53, "hurt_facing_right", 0xFF, 6),
-    (0x54, "hurt_facing_left", 0xFF, 6),
-    (0x55, "jump_transition_facing_right_aiming_up", 0xFF, 3),
-    (0x56, "jump_transition_facing_left_aiming_up", 0xFF, 3),
-    (0x57, "jump_transition_facing_right_aiming_upright", 0xFF, 3),
-    (0x58, "jump_transition_facing_left_aiming_upleft", 0xFF, 3),
-    (0x59, "jump_transition_facing_right_aiming_downright", 0xFF, 3),
-    (0x5A, "jump_transition_facing_left_aiming_downleft", 0xFF, 3),
-    (0x67, "falling_facing_right_fired_a_shot", 0xFF, 8),
-    (0x68, "falling_facing_left_fired_a_shot", 0xFF, 8),
-    (0x69, "jump_facing_right_moving_or_not_aiming_upright", 0x51, 8),
-    (0x6A, "jump_facing_left_moving_or_not_aiming_upleft", 0x52, 8),
-    (0x6B, "jump_facing_right_moving_or_not_aiming_downright", 0x51, 8),
-    (0x6C, "jump_facing_left_moving_or_not_aiming_downleft", 0x52, 8),
-    (0x6D, "falling_facing_right_aiming_upright", 0x29, 8),
-    (0x6E, "falling_facing_left_aiming_upleft", 0x2A, 8),
-    (0x6F, "falling_facing_right_aiming_downright", 0x29, 8),
-    (0x70, "falling_facing_left_aiming_downleft", 0x2A, 8),
-    (0x71, "standing_to_crouching_facing_right_aiming_upright", 0x27, 0),
-    (0x72, "standing_to_crouching_facing_left_aiming_upleft", 0x28, 0),
-    (0x73, "standing_to_crouching_facing_right_aiming_downright", 0x27, 0),
-    (0x74, "standing_to_crouching_facing_left_aiming_downleft", 0x28, 0),
-    (0x75, "moonwalk_right_aiming_upleft", 0x06, 6),
-    (0x76, "moonwalk_left_aiming_upright", 0x05, 6),
-    (0x77, "moonwalk_right_aiming_downleft", 0x08, 6),
-    (0x78, "moonwalk_left_aiming_downright", 0x07, 6),
-    (0x79, "springball_facing_right", 0xFF, 0),
-    (0x7A, "springball_facing_left", 0xFF, 0),
-    (0x7B, "springball_moving_right", 0x79, 0),
-    (0x7C, "springball_moving_left", 0x7A, 0),
-    (0x7D, "springball_falling_right", 0xFF, 0),
-    (0x7E, "springball_falling_left", 0xFF, 0),
-    (0x7F, "springball_jump_right", 0xFF, 0),
-    (0x80, "springball_jump_left", 0xFF, 0),
-    (0x81, "screw_attack_right", 0xFF, 0),
-    (0x82, "screw_attack_left", 0xFF, 0),
-    (0x83, "wall_jump_right", 0x19, 8),
-    (0x84, "wall_jump_left", 0x1A, 8),
-    (0x85, "crouching_right_aiming_up", 0x27, 0),
-    (0x86, "crouching_left_aiming_up", 0x28, 0),
-    (0x87, "falling_turn_right_to_left", 0xFF, 8),
-    (0x88, "falling_turn_left_to_right", 0xFF, 8),
-    (0x89, "ran_into_wall_right", 0xFF, 6),
-    (0x8A, "ran_into_wall_left", 0xFF, 6),
-    (0x8D, "aiming_diagonal_down_turn_right_to_left", 0xFF, 6),
-    (0x8E, "aiming_diagonal_down_turn_left_to_right", 0xFF, 6),
-    (0x8F, "aiming_up_turn_right_to_left", 0xFF, 8),
-    (0x90, "aiming_up_turn_left_to_right", 0xFF, 8),
-    (0x91, "midair_aiming_down_diagonal_down_turn_right_to_left", 0xFF, 8),
-    (0x92, "midair_aiming_down_diagonal_down_turn_left_to_right", 0xFF, 8),
-    (0x93, "falling_aiming_up_turn_right_to_left", 0xFF, 8),
-    (0x94, "falling_aiming_up_turn_left_to_right", 0xFF, 8),
-    (0x95, "falling_aiming_down_diagonal_down_turn_right_to_left", 0xFF, 8),
-    (0x96, "falling_aiming_down_diagonal_down_turn_left_to_right", 0xFF, 8),
-    (0x97, "crouching_aiming_up_turn_right_to_left", 0x28, 0),
-    (0x98, "crouching_aiming_up_turn_left_to_right", 0x28, 0),
-    (0x99, "crouching_aiming_diagonal_down_turn_right_to_left", 0x28, 0),
-    (0x9A, "crouching_aiming_diagonal_down_turn_left_to_right", 0x28, 0),
-    (0x9B, "elevator_pose_with_varia_gravity_suit", 0xFF, 8),
-    (0x9C, "standing_aiming_diagonal_up_turn_right_to_left", 0xFF, 6),
-    (0x9D, "standing_aiming_diagonal_up_turn_left_to_right", 0xFF, 6),
-    (0x9E, "midair_aiming_diagonal_up_turn_right_to_left", 0xFF, 8),
-    (0x9F, "midair_aiming_diagonal_up_turn_left_to_right", 0xFF, 8),
-    (0xA0, "falling_aiming_diagonal_up_turn_right_to_left", 0xFF, 8),
-    (0xA1, "falling_aiming_diagonal_up_turn_left_to_right", 0xFF, 8),
-    (0xA2, "crouching_aiming_diagonal_up_turn_right_to_left", 0x28, 0),
-    (0xA3, "crouching_aiming_diagonal_up_turn_left_to_right", 0x28, 0),
-    (0xA4, "landing_facing_right", 0xFF, 3),
-    (0xA5, "landing_facing_left", 0xFF, 3),
-    (0xA6, "landing_spinjump_facing_right", 0xFF, 3),
-    (0xA7, "landing_spinjump_facing_left", 0xFF, 3),
-    (0xB2, "grapple_clockwise", 0xB2, 10),
-    (0xB3, "grapple_counterclockwise", 0xB3, 10),
-    (0xB8, "grapple_on_right_wall_facing_left", 0xFF, 0),
-    (0xB9, "grapple_on_left_wall_facing_right", 0xFF, 0),
-    (0xBA, "grabbed_by_draygon_facing_left", 0xFF, 6),
-    (0xBB, "grabbed_by_draygon_facing_left_aiming_upleft", 0xBA, 6),
-    (0xBC, "grabbed_by_draygon_facing_left_firing", 0xBA, 6),
-    (0xBD, "grabbed_by_draygon_facing_left_aiming_downleft", 0xBA, 6),
-    (0xBE, "grabbed_by_draygon_facing_left_moving", 0xBA, 6),
-    (0xC7, "super_jump_windup_right", 0xFF, 8),
-    (0xC8, "super_jump_windup_left", 0xFF, 8),
-    (0xC9, "super_jump_horizontal_right", 0xFF, 8),
-    (0xCA, "super_jump_horizontal_left", 0xFF, 8),
-    (0xCB, "super_jump_vertical_right", 0xFF, 8),
-    (0xCC, "super_jump_vertical_left", 0xFF, 8),
-    (0xCD, "super_jump_diagonal_right", 0xFF, 8),
-    (0xCE, "super_jump_diagonal_left", 0xFF, 8),
-    (0xCF, "ran_into_wall_right_aiming_diagonal_down", 0x89, 6),
-    (0xD0, "ran_into_wall_left_aiming_diagonal_up", 0x8A, 6),
-    (0xD1, "ran_into_wall_right_aiming_diagonal_up", 0x89, 6),
-    (0xD2, "ran_into_wall_left_aiming_diagonal_down", 0x8A, 6),
-    (0xD3, "crystal_flash_right", 0xFF, 6),
-    (0xD4, "crystal_flash_left", 0xFF, 6),
-    (0xD5, "xray_right", 0xFF, 6),
-    (0xD6, "xray_left", 0xFF, 6),
-    (0xD7, "crystal_flash_right_ending", 0xFF, 6),
-    (0xD8, "crystal_flash_ending_left", 0xFF, 6),
-    (0xD9, "xray_crouching_right", 0xFF, 0),
-    (0xDA, "xray_crouching_left", 0xFF, 0),
-    (0xE0, "landing_facing_right_aiming_up", 0xFF, 3),
-    (0xE1, "landing_facing_left_aiming_up", 0xFF, 3),
-    (0xE2, "landing_facing_right_aiming_upright", 0xFF, 3),
-    (0xE3, "landing_facing_left_aiming_upleft", 0xFF, 3),
-    (0xE4, "landing_facing_right_aiming_downright", 0xFF, 3),
-    (0xE5, "landing_facing_left_aiming_downleft", 0xFF, 3),
-    (0xE6, "landing_facing_right_firing", 0xFF, 3),
-    (0xE7, "landing_facing_left_firing", 0xFF, 3),
-    (0xE8, "exhausted_facing_right", 0xFF, 0xFC),
-    (0xE9, "exhausted_facing_left", 0xFF, 0xFC),
-    (0xEA, "exhausted_facing_right_looking_up", 0xFF, 0xFC),
-    (0xEB, "exhausted_facing_left_looking_up", 0xFF, 0xFC),
-    (0xEC, "grabbed_by_draygon_facing_right", 0xFF, 6),
-    (0xED, "grabbed_by_draygon_facing_right_aiming_upright", 0xEC, 6),
-    (0xEE, "grabbed_by_draygon_facing_right_firing", 0xEC, 6),
-    (0xEF, "grabbed_by_draygon_facing_right_aiming_downleft", 0xEC, 6),
-    (0xF0, "grabbed_by_draygon_facing_right_moving", 0xEC, 6),
-    (0xF1, "crouch_transition_right_aiming_up", 0xFF, 8),
-    (0xF2, "crouch_transition_left_aiming_up", 0xFF, 8),
-    (0xF3, "crouch_transition_right_aiming_upright", 0xFF, 8),
-    (0xF4, "crouch_transition_left_aiming_upleft", 0xFF, 8),
-    (0xF5, "crouch_transition_right_aiming_downright", 0xFF, 8),
-    (0xF6, "crouch_transition_left_aiming_downleft", 0xFF, 8),
-    (0xF7, "crouching_to_standing_right_aiming_up", 0xFF, 3),
-    (0xF8, "crouching_to_standing_left_aiming_up", 0xFF, 3),
-    (0xF9, "crouching_to_standing_right_aiming_upright", 0xFF, 3),
-    (0xFA, "crouching_to_standing_left_aiming_upleft", 0xFF, 3),
-    (0xFB, "crouching_to_standing_right_aiming_downright", 0xFF, 3),
-    (0xFC, "crouching_to_standing_left_aiming_downleft", 0xFF, 3),
-];
+use sm::samus;
+use sm::snes::Rom;
+
+/// (state, name, default state or 0xFF) for every pose in the ROM
+pub fn all(rom: &Rom) -> Vec<(usize, String, usize)> {
+    samus::poses(rom).into_iter()
+        .map(|pose| (pose.state, pose.name, pose.default_state.unwrap_or(0xFF)))
+        .collect()
+}
@@ -0,0 +1,296 @@
+// Indexed color Aseprite files. The format's documented at
+// https://github.com/aseprite/aseprite/blob/main/docs/ase-file-specs.md
+// and all of it is little endian chunks: a 128 byte header, then every frame
+// with its own header and chunks. The first frame also carries the palette,
+// the layers and the tags. Cels get written uncompressed, which Aseprite is
+// happy to read and saves pulling in zlib for a few kilobytes.
+
+use std::cmp;
+use std::io::{self, Write};
+use byteorder::{LittleEndian, WriteBytesExt};
+use atlas::{milliseconds, Tag};
+use centered_canvas;
+use frame_map::{CompositedFrame, Rect};
+use sprite::Sprite;
+use util::{palette_rows, ColorConversion};
+
+const FILE_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
+const HEADER_SIZE: usize = 128;
+const INDEXED: u16 = 8;
+
+const LAYER_CHUNK: u16 = 0x2004;
+const CEL_CHUNK: u16 = 0x2005;
+const TAGS_CHUNK: u16 = 0x2018;
+const PALETTE_CHUNK: u16 = 0x2019;
+
+const RAW_CEL: u16 = 0;
+const LINKED_CEL: u16 = 1;
+
+/// Hitbox and origin layers draw with this palette index, same as the
+/// viewer's hitbox outlines
+pub const OVERLAY_COLOR: u8 = 0x0F;
+// How far the origin's crosshair reaches out from the middle
+const CROSSHAIR: i16 = 2;
+
+/// Writes every frame of `sprite` as a frame of an Aseprite file, with
+/// `tags` naming runs of frames. `hitboxes` has each frame's hitboxes
+/// (relative to its origin) for a layer of their own, and `origin` adds a
+/// layer with a crosshair on the origin.
+pub fn write_aseprite<W: Write>(mut w: W, sprite: &Sprite, tags: &[Tag], hitboxes: Option<&[Vec<Rect>]>, origin: bool) -> io::Result<()> {
+    let frames = sprite.frames();
+
+    // Canvas big enough for the sprite, the hitboxes and the crosshair, and
+    // where the origin goes on it
+    let (zx, zy) = sprite.zero();
+    let (mut left, mut top) = (zx as i16, zy as i16);
+    let (mut right, mut bottom) = (sprite.width() as i16 - left, sprite.height() as i16 - top);
+    for &(l, t, r, b) in hitboxes.iter().flat_map(|h| h.iter()).flat_map(|rects| rects.iter()) {
+        left = cmp::max(left, l.saturating_neg());
+        top = cmp::max(top, t.saturating_neg());
+        right = cmp::max(right, r.saturating_add(1));
+        bottom = cmp::max(bottom, b.saturating_add(1));
+    }
+    if origin {
+        left = cmp::max(left, CROSSHAIR);
+        top = cmp::max(top, CROSSHAIR);
+        right = cmp::max(right, CROSSHAIR + 1);
+        bottom = cmp::max(bottom, CROSSHAIR + 1);
+    }
+    let (width, height) = (left as u16 + right as u16, top as u16 + bottom as u16);
+    let zero = (left, top);
+
+    let mut layers = vec!["sprite"];
+    if hitboxes.is_some() {
+        layers.push("hitboxes");
+    }
+    if origin {
+        layers.push("origin");
+    }
+
+    let mut body = Vec::new();
+    for (n, frame) in frames.iter().enumerate() {
+        let mut chunks: Vec<Vec<u8>> = Vec::new();
+        if n == 0 {
+            chunks.push(palette_chunk(sprite.palette(), sprite.color_conversion()));
+            for name in layers.iter() {
+                chunks.push(layer_chunk(name));
+            }
+            if !tags.is_empty() {
+                chunks.push(tags_chunk(tags));
+            }
+        }
+
+        if frame.width > 0 && frame.height > 0 {
+            let position = (zero.0 - frame.zero_x as i16, zero.1 - frame.zero_y as i16);
+            chunks.push(raw_cel(0, position, frame.width, frame.height, &frame.buffer));
+        }
+        if let Some(hitboxes) = hitboxes {
+            if let Some(rects) = hitboxes.get(n).filter(|rects| !rects.is_empty()) {
+                chunks.push(hitbox_cel(1, rects, width, height, zero));
+            }
+        }
+        if origin {
+            let layer = layers.len() as u16 - 1;
+            chunks.push(if n == 0 { crosshair_cel(layer, zero) } else { linked_cel(layer, 0) });
+        }
+
+        write_frame(&mut body, frame, &chunks)?;
+    }
+
+    write_header(&mut w, (HEADER_SIZE + body.len()) as u32, frames.len() as u16, width, height, sprite.palette())?;
+    w.write_all(&body)
+}
+
+fn write_header<W: Write>(w: &mut W, file_size: u32, num_frames: u16, width: u16, height: u16, palette: &[u16]) -> io::Result<()> {
+    let num_colors = palette_rows(palette).len() as u16;
+    w.write_u32::<LittleEndian>(file_size)?;
+    w.write_u16::<LittleEndian>(FILE_MAGIC)?;
+    w.write_u16::<LittleEndian>(num_frames)?;
+    w.write_u16::<LittleEndian>(width)?;
+    w.write_u16::<LittleEndian>(height)?;
+    w.write_u16::<LittleEndian>(INDEXED)?;
+    w.write_u32::<LittleEndian>(1)?; // layer opacity is valid
+    w.write_u16::<LittleEndian>(100)?; // deprecated speed, frames have their own
+    w.write_u32::<LittleEndian>(0)?;
+    w.write_u32::<LittleEndian>(0)?;
+    w.write_u8(0)?; // transparent index
+    w.write_all(&[0; 3])?;
+    w.write_u16::<LittleEndian>(num_colors)?;
+    w.write_u8(1)?; // square pixels
+    w.write_u8(1)?;
+    w.write_i16::<LittleEndian>(0)?; // 8x8 grid, one per tile
+    w.write_i16::<LittleEndian>(0)?;
+    w.write_u16::<LittleEndian>(8)?;
+    w.write_u16::<LittleEndian>(8)?;
+    w.write_all(&[0; 84])
+}
+
+fn write_frame(body: &mut Vec<u8>, frame: &CompositedFrame, chunks: &[Vec<u8>]) -> io::Result<()> {
+    let size: usize = 16 + chunks.iter().map(|c| c.len()).sum::<usize>();
+    body.write_u32::<LittleEndian>(size as u32)?;
+    body.write_u16::<LittleEndian>(FRAME_MAGIC)?;
+    body.write_u16::<LittleEndian>(cmp::min(chunks.len(), 0xFFFF) as u16)?;
+    // Anything past a minute or so gets cut short rather than wrapping round
+    let duration = cmp::min(cmp::max(1, milliseconds(frame.duration)), u16::MAX as u32);
+    body.write_u16::<LittleEndian>(duration as u16)?;
+    body.write_all(&[0; 2])?;
+    body.write_u32::<LittleEndian>(chunks.len() as u32)?;
+    for chunk in chunks {
+        body.write_all(chunk)?;
+    }
+    Ok(())
+}
+
+// Chunks are built in memory and start with their size, so everything below
+// writes into a Vec where writes can't fail
+
+fn chunk(kind: u16, data: Vec<u8>) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(data.len() + 6);
+    chunk.write_u32::<LittleEndian>(data.len() as u32 + 6).unwrap();
+    chunk.write_u16::<LittleEndian>(kind).unwrap();
+    chunk.extend(data);
+    chunk
+}
+
+fn write_string(data: &mut Vec<u8>, s: &str) {
+    data.write_u16::<LittleEndian>(s.len() as u16).unwrap();
+    data.extend_from_slice(s.as_bytes());
+}
+
+fn palette_chunk(palette: &[u16], conversion: ColorConversion) -> Vec<u8> {
+    let colors = palette_rows(palette);
+    let mut data = Vec::new();
+    data.write_u32::<LittleEndian>(colors.len() as u32).unwrap();
+    data.write_u32::<LittleEndian>(0).unwrap();
+    data.write_u32::<LittleEndian>(colors.len() as u32 - 1).unwrap();
+    data.extend_from_slice(&[0; 8]);
+    for (i, color) in colors.iter().enumerate() {
+        let (r, g, b) = conversion.rgb888(color);
+        data.write_u16::<LittleEndian>(0).unwrap(); // no name
+        data.extend_from_slice(&[r, g, b, if i == 0 { 0 } else { 0xFF }]);
+    }
+    chunk(PALETTE_CHUNK, data)
+}
+
+fn layer_chunk(name: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.write_u16::<LittleEndian>(1 | 2).unwrap(); // visible, editable
+    data.write_u16::<LittleEndian>(0).unwrap(); // normal layer
+    data.write_u16::<LittleEndian>(0).unwrap(); // child level
+    data.write_u16::<LittleEndian>(0).unwrap(); // ignored width and height
+    data.write_u16::<LittleEndian>(0).unwrap();
+    data.write_u16::<LittleEndian>(0).unwrap(); // normal blend mode
+    data.write_u8(0xFF).unwrap();
+    data.extend_from_slice(&[0; 3]);
+    write_string(&mut data, name);
+    chunk(LAYER_CHUNK, data)
+}
+
+fn tags_chunk(tags: &[Tag]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.write_u16::<LittleEndian>(tags.len() as u16).unwrap();
+    data.extend_from_slice(&[0; 8]);
+    for tag in tags {
+        data.write_u16::<LittleEndian>(tag.from as u16).unwrap();
+        data.write_u16::<LittleEndian>(tag.to as u16).unwrap();
+        data.write_u8(0).unwrap(); // forward
+        data.write_u16::<LittleEndian>(0).unwrap(); // repeat forever
+        data.extend_from_slice(&[0; 6]);
+        data.extend_from_slice(&[0, 0, 0, 0]); // deprecated color, and a spare byte
+        write_string(&mut data, &tag.name);
+    }
+    chunk(TAGS_CHUNK, data)
+}
+
+fn cel_header(layer: u16, (x, y): (i16, i16), kind: u16) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.write_u16::<LittleEndian>(layer).unwrap();
+    data.write_i16::<LittleEndian>(x).unwrap();
+    data.write_i16::<LittleEndian>(y).unwrap();
+    data.write_u8(0xFF).unwrap();
+    data.write_u16::<LittleEndian>(kind).unwrap();
+    data.write_i16::<LittleEndian>(0).unwrap(); // z-index
+    data.extend_from_slice(&[0; 5]);
+    data
+}
+
+fn raw_cel(layer: u16, position: (i16, i16), width: u16, height: u16, pixels: &[u8]) -> Vec<u8> {
+    let mut data = cel_header(layer, position, RAW_CEL);
+    data.write_u16::<LittleEndian>(width).unwrap();
+    data.write_u16::<LittleEndian>(height).unwrap();
+    data.extend_from_slice(pixels);
+    chunk(CEL_CHUNK, data)
+}
+
+fn linked_cel(layer: u16, frame: u16) -> Vec<u8> {
+    let mut data = cel_header(layer, (0, 0), LINKED_CEL);
+    data.write_u16::<LittleEndian>(frame).unwrap();
+    chunk(CEL_CHUNK, data)
+}
+
+fn hitbox_cel(layer: u16, rects: &[Rect], width: u16, height: u16, zero: (i16, i16)) -> Vec<u8> {
+    let mut pixels = vec![0; width as usize * height as usize];
+    for &rect in rects {
+        centered_canvas::paint_outline(&mut pixels, width, (zero.0 as u16, zero.1 as u16), rect, OVERLAY_COLOR);
+    }
+    raw_cel(layer, (0, 0), width, height, &pixels)
+}
+
+fn crosshair_cel(layer: u16, zero: (i16, i16)) -> Vec<u8> {
+    let size = (CROSSHAIR * 2 + 1) as u16;
+    let mut pixels = vec![0; size as usize * size as usize];
+    for i in 0..size as usize {
+        pixels[CROSSHAIR as usize * size as usize + i] = OVERLAY_COLOR;
+        pixels[i * size as usize + CROSSHAIR as usize] = OVERLAY_COLOR;
+    }
+    raw_cel(layer, (zero.0 - CROSSHAIR, zero.1 - CROSSHAIR), size, size, &pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{ByteOrder, LittleEndian};
+    use atlas::Tag;
+    use frame_map::CompositedFrame;
+    use sprite::Sprite;
+    use super::write_aseprite;
+
+    #[test]
+    fn frames_and_sizes_add_up() {
+        let palette = [0u16; 16];
+        let sprite = Sprite::new(vec![CompositedFrame::test_pattern(4, 4, 6), CompositedFrame::test_pattern(4, 4, 12)], &palette);
+        let tags = [Tag { name: "idle".to_string(), from: 0, to: 1 }];
+        let hitboxes = [vec![(-3, -3, 3, 3)], vec![]];
+        let mut file = Vec::new();
+        write_aseprite(&mut file, &sprite, &tags, Some(&hitboxes), true).unwrap();
+
+        assert_eq!(LittleEndian::read_u32(&file[0..4]) as usize, file.len());
+        assert_eq!(LittleEndian::read_u16(&file[4..6]), 0xA5E0);
+        assert_eq!(LittleEndian::read_u16(&file[6..8]), 2);
+        // Grown to fit the hitbox
+        assert_eq!((LittleEndian::read_u16(&file[8..10]), LittleEndian::read_u16(&file[10..12])), (7, 7));
+
+        let first = LittleEndian::read_u32(&file[128..132]) as usize;
+        assert_eq!(LittleEndian::read_u16(&file[132..134]), 0xF1FA);
+        assert_eq!(LittleEndian::read_u16(&file[136..138]), 100);
+        // palette, 3 layers, tags, then sprite, hitbox and origin cels
+        assert_eq!(LittleEndian::read_u32(&file[140..144]), 8);
+
+        let second = &file[128 + first..];
+        assert_eq!(LittleEndian::read_u32(&second[0..4]) as usize, second.len());
+        assert_eq!(LittleEndian::read_u16(&second[8..10]), 200);
+        assert_eq!(LittleEndian::read_u32(&second[12..16]), 2);
+    }
+
+    #[test]
+    fn garbage_durations_and_hitboxes_stay_in_range() {
+        let palette = [0u16; 16];
+        let sprite = Sprite::new(vec![CompositedFrame::test_pattern(4, 4, 0xFFFF)], &palette);
+        let hitboxes = [vec![(i16::MIN, 0, 0, 0)]];
+        let mut file = Vec::new();
+        write_aseprite(&mut file, &sprite, &[], Some(&hitboxes), false).unwrap();
+
+        assert_eq!((LittleEndian::read_u16(&file[8..10]), LittleEndian::read_u16(&file[10..12])), (0x7FFF + 2, 4));
+        assert_eq!(LittleEndian::read_u16(&file[136..138]), u16::MAX);
+    }
+}
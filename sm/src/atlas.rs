@@ -0,0 +1,305 @@
+// Texture atlases. Every frame gets trimmed down to its opaque pixels,
+// packed onto one indexed image, and described in the JSON array layout
+// TexturePacker and Aseprite both write: frame rects, trim info, durations
+// in milliseconds and frame tags. The sprite's origin goes in as a pivot and
+// as `origin`, in pixels from the top left of the untrimmed frame. The image
+// is indexed, with the sprite's palette, so the JSON leaves out TexturePacker's
+// `format` (none of its pixel formats are indexed).
+
+use std::cmp;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Write};
+use frame_map::CompositedFrame;
+use export;
+use util::ColorConversion;
+
+// Transparent gap between frames, so filtering doesn't bleed neighbors in
+const PADDING: usize = 1;
+
+pub struct AtlasFrame {
+    pub name: String,
+    /// Where the trimmed frame is in the atlas
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    /// Where the trimmed frame was in the original one
+    pub trim_x: usize,
+    pub trim_y: usize,
+    pub source_width: usize,
+    pub source_height: usize,
+    pub zero_x: u16,
+    pub zero_y: u16,
+    pub duration: u16,
+}
+
+/// Frames `from` to `to` (inclusive) make up the animation `name`
+pub struct Tag {
+    pub name: String,
+    pub from: usize,
+    pub to: usize,
+}
+
+pub struct Atlas {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    pub frames: Vec<AtlasFrame>,
+    pub tags: Vec<Tag>,
+}
+
+struct Trimmed<'a> {
+    frame: &'a CompositedFrame,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+fn trim<'a>(frame: &'a CompositedFrame) -> Trimmed<'a> {
+    let width = frame.width as usize;
+    let (mut left, mut top, mut right, mut bottom) = (width, frame.height as usize, 0, 0);
+    for (i, &px) in frame.buffer.iter().enumerate() {
+        if px == 0 {
+            continue;
+        }
+        let (x, y) = (i % width, i / width);
+        left = cmp::min(left, x);
+        top = cmp::min(top, y);
+        right = cmp::max(right, x + 1);
+        bottom = cmp::max(bottom, y + 1);
+    }
+    if right == 0 {
+        // Nothing to see, keep a 0x0 frame around so the indices still line up
+        return Trimmed { frame, x: 0, y: 0, width: 0, height: 0 };
+    }
+    Trimmed { frame, x: left, y: top, width: right - left, height: bottom - top }
+}
+
+/// Packs every animation's frames into one atlas, each animation getting a
+/// tag named after it. Frames are named `"<animation> <n>"`. Fails if
+/// there's nothing to draw, since that'd make an empty image.
+pub fn pack(animations: &[(&str, &[CompositedFrame])]) -> io::Result<Atlas> {
+    let mut trimmed = Vec::new();
+    let mut names = Vec::new();
+    let mut tags = Vec::new();
+    for &(name, frames) in animations.iter() {
+        if frames.is_empty() {
+            continue;
+        }
+        tags.push(Tag { name: name.to_string(), from: trimmed.len(), to: trimmed.len() + frames.len() - 1 });
+        for (n, frame) in frames.iter().enumerate() {
+            trimmed.push(trim(frame));
+            names.push(format!("{} {}", name, n));
+        }
+    }
+
+    if trimmed.iter().all(|t| t.width == 0 || t.height == 0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "every frame is empty"));
+    }
+
+    let (width, positions) = shelf_pack(&trimmed);
+    let height = positions.iter().zip(trimmed.iter())
+        .map(|(&(_, y), t)| y + t.height)
+        .max()
+        .unwrap_or(0);
+
+    let mut pixels = vec![0u8; width * height];
+    for (t, &(x, y)) in trimmed.iter().zip(positions.iter()) {
+        let source_width = t.frame.width as usize;
+        for row in 0..t.height {
+            let from = (t.y + row) * source_width + t.x;
+            let to = (y + row) * width + x;
+            pixels[to..to + t.width].copy_from_slice(&t.frame.buffer[from..from + t.width]);
+        }
+    }
+
+    let frames = trimmed.iter().zip(positions.iter()).zip(names)
+        .map(|((t, &(x, y)), name)| AtlasFrame {
+            name,
+            x,
+            y,
+            width: t.width,
+            height: t.height,
+            trim_x: t.x,
+            trim_y: t.y,
+            source_width: t.frame.width as usize,
+            source_height: t.frame.height as usize,
+            zero_x: t.frame.zero_x,
+            zero_y: t.frame.zero_y,
+            duration: t.frame.duration,
+        })
+        .collect();
+
+    Ok(Atlas {
+        width,
+        height,
+        pixels,
+        frames,
+        tags,
+    })
+}
+
+// First fit decreasing height: tallest frames first, each going on the
+// first shelf with room for it. The atlas is as wide as a square holding
+// every frame would be, rounded up to a power of two.
+fn shelf_pack(frames: &[Trimmed]) -> (usize, Vec<(usize, usize)>) {
+    let area: usize = frames.iter().map(|t| (t.width + PADDING) * (t.height + PADDING)).sum();
+    let widest = frames.iter().map(|t| t.width + PADDING).max().unwrap_or(0);
+    let mut width = 1;
+    while width * width < area || width < widest {
+        width *= 2;
+    }
+
+    let mut order: Vec<usize> = (0..frames.len()).collect();
+    order.sort_by(|&a, &b| frames[b].height.cmp(&frames[a].height));
+
+    // (y, height, filled width) of each shelf
+    let mut shelves: Vec<(usize, usize, usize)> = Vec::new();
+    let mut positions = vec![(0, 0); frames.len()];
+    for index in order {
+        let (w, h) = (frames[index].width, frames[index].height);
+        if w == 0 || h == 0 {
+            continue;
+        }
+        let (w, h) = (w + PADDING, h + PADDING);
+        let shelf = shelves.iter().position(|&(_, height, filled)| h <= height && filled + w <= width);
+        let shelf = match shelf {
+            Some(shelf) => shelf,
+            None => {
+                let y = shelves.last().map(|&(y, height, _)| y + height).unwrap_or(0);
+                shelves.push((y, h, 0));
+                shelves.len() - 1
+            },
+        };
+        let (y, _, ref mut filled) = shelves[shelf];
+        positions[index] = (*filled, y);
+        *filled += w;
+    }
+    (width, positions)
+}
+
+impl Atlas {
+    pub fn write_png<W: Write>(&self, w: W, palette: &[u16], conversion: ColorConversion) -> io::Result<()> {
+        export::write_png(w, self.width as u32, self.height as u32, &self.pixels, palette, conversion)
+    }
+
+    /// TexturePacker's "JSON (Array)" layout, with Aseprite's `frameTags`
+    pub fn to_json(&self, image: &str) -> String {
+        let mut json = String::new();
+        json.push_str("{\n  \"frames\": [\n");
+        for (n, f) in self.frames.iter().enumerate() {
+            let (pivot_x, pivot_y) = if f.source_width == 0 || f.source_height == 0 {
+                (0.0, 0.0)
+            } else {
+                (f.zero_x as f64 / f.source_width as f64, f.zero_y as f64 / f.source_height as f64)
+            };
+            write!(json,
+                "    {{\n      \"filename\": {},\n      \"frame\": {{ \"x\": {}, \"y\": {}, \"w\": {}, \"h\": {} }},\n      \
+                \"rotated\": false,\n      \"trimmed\": {},\n      \
+                \"spriteSourceSize\": {{ \"x\": {}, \"y\": {}, \"w\": {}, \"h\": {} }},\n      \
+                \"sourceSize\": {{ \"w\": {}, \"h\": {} }},\n      \
+                \"pivot\": {{ \"x\": {}, \"y\": {} }},\n      \
+                \"origin\": {{ \"x\": {}, \"y\": {} }},\n      \
+                \"duration\": {}\n    }}{}\n",
+                json_string(&f.name), f.x, f.y, f.width, f.height,
+                f.width != f.source_width || f.height != f.source_height,
+                f.trim_x, f.trim_y, f.width, f.height,
+                f.source_width, f.source_height,
+                pivot_x, pivot_y,
+                f.zero_x, f.zero_y,
+                milliseconds(f.duration),
+                if n + 1 < self.frames.len() { "," } else { "" },
+            ).unwrap();
+        }
+        json.push_str("  ],\n  \"meta\": {\n");
+        write!(json,
+            "    \"app\": \"sm\",\n    \"image\": {},\n    \
+            \"size\": {{ \"w\": {}, \"h\": {} }},\n    \"scale\": \"1\",\n    \"frameTags\": [\n",
+            json_string(image), self.width, self.height,
+        ).unwrap();
+        for (n, tag) in self.tags.iter().enumerate() {
+            writeln!(json,
+                "      {{ \"name\": {}, \"from\": {}, \"to\": {}, \"direction\": \"forward\" }}{}",
+                json_string(&tag.name), tag.from, tag.to,
+                if n + 1 < self.tags.len() { "," } else { "" },
+            ).unwrap();
+        }
+        json.push_str("    ]\n  }\n}\n");
+        json
+    }
+}
+
+/// Frame durations are in 60Hz ticks
+pub fn milliseconds(ticks: u16) -> u32 {
+    (ticks as u32 * 1000 + 30) / 60
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::pack;
+    use frame_map::CompositedFrame;
+
+    #[test]
+    fn frames_are_trimmed_and_dont_overlap() {
+        let walk = vec![CompositedFrame::test_pattern(10, 20, 4), CompositedFrame::test_pattern(12, 18, 4), CompositedFrame::test_pattern(6, 6, 8)];
+        let idle = vec![CompositedFrame::test_pattern(30, 8, 60)];
+        let atlas = pack(&[("walk", &walk), ("idle", &idle)]).unwrap();
+
+        assert_eq!(atlas.frames.len(), 4);
+        assert_eq!(atlas.tags.len(), 2);
+        assert_eq!((atlas.tags[1].from, atlas.tags[1].to), (3, 3));
+
+        let f = &atlas.frames[0];
+        assert_eq!((f.width, f.height, f.trim_x, f.trim_y), (8, 18, 1, 1));
+        assert_eq!((f.source_width, f.source_height, f.zero_x, f.zero_y), (10, 20, 5, 10));
+
+        for (i, a) in atlas.frames.iter().enumerate() {
+            assert!(a.x + a.width <= atlas.width && a.y + a.height <= atlas.height);
+            for b in atlas.frames.iter().skip(i + 1) {
+                let apart = a.x + a.width <= b.x || b.x + b.width <= a.x
+                    || a.y + a.height <= b.y || b.y + b.height <= a.y;
+                assert!(apart, "{} overlaps {}", a.name, b.name);
+            }
+        }
+
+        // Pixels came along with their frames
+        let f = &atlas.frames[3];
+        assert_eq!(atlas.pixels[f.y * atlas.width + f.x], 2);
+    }
+
+    #[test]
+    fn json_has_frames_and_tags() {
+        let frames = vec![CompositedFrame::test_pattern(4, 4, 6)];
+        let json = pack(&[("pose \"01\"", &frames)]).unwrap().to_json("atlas.png");
+        assert!(json.contains("\"filename\": \"pose \\\"01\\\" 0\""));
+        assert!(json.contains("\"duration\": 100"));
+        assert!(json.contains("\"origin\": { \"x\": 2, \"y\": 2 }"));
+        assert!(json.contains("\"frameTags\""));
+        assert!(!json.contains("\"format\""));
+    }
+
+    #[test]
+    fn nothing_to_draw_is_an_error() {
+        let blank = CompositedFrame { buffer: vec![0; 4], ..CompositedFrame::test_pattern(2, 2, 1) };
+        let err = pack(&[("blank", &[blank][..]), ("none", &[])]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
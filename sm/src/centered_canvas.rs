@@ -1,64 +1,72 @@
 // http://old.metroidconstruction.com/tilemapediting.php
 use snes_bitplanes::Tile;
 
-fn _paint_tile(buffer: &mut [u8], tile: &Tile, width: usize, offset: usize, flip_x: bool, flip_y: bool) {
-    let mut index = offset;
-    if flip_y {
-        for row in tile.chunks(8).rev() {
-            _paint_row(buffer, row, index, flip_x);
-            index += width as usize;
-        }
-    } else {
-        for row in tile.chunks(8) {
-            _paint_row(buffer, row, index, flip_x);
-            index += width as usize;
-        }
-    }
+/// A buffer to paint parts onto, along with a buffer for their priorities.
+/// Positions are relative to `zero`.
+pub struct Canvas<'a> {
+    pub buffer: &'a mut [u8],
+    pub priorities: &'a mut [u8],
+    pub width: u16,
+    pub zero: (u16, u16),
 }
 
-fn _paint_row(buffer: &mut [u8], row: &[u8], offset: usize, flip_x: bool) {
-    if flip_x {
-        for (n, px) in row.iter().rev().enumerate() {
-            if *px == 0 {
+impl<'a> Canvas<'a> {
+    pub fn paint_tile(&mut self, tile: &Tile, (x, y): (i16, i16), flip_x: bool, flip_y: bool, attributes: (u8, u8)) {
+        let pos = position(self.zero.0, self.zero.1, x, y);
+        self.paint_at(tile, pos, flip_x, flip_y, attributes);
+    }
+
+    pub fn paint_block(&mut self, (tile0, tile1, tile2, tile3): (&Tile, &Tile, &Tile, &Tile), (x, y): (i16, i16), flip_x: bool, flip_y: bool, attributes: (u8, u8)) {
+        let (x, y) = position(self.zero.0, self.zero.1, x, y);
+        self.paint_at(tile0, (x, y), flip_x, flip_y, attributes);
+        self.paint_at(tile1, (x + 8, y), flip_x, flip_y, attributes);
+        self.paint_at(tile2, (x, y + 8), flip_x, flip_y, attributes);
+        self.paint_at(tile3, (x + 8, y + 8), flip_x, flip_y, attributes);
+    }
+
+    // Pixels end up as `palette row * 16 + color`, with the part's priority
+    // written alongside in `priorities`. Anything that lands outside the buffer
+    // is clipped.
+    fn paint_at(&mut self, tile: &Tile, (x, y): (i32, i32), flip_x: bool, flip_y: bool, (palette, priority): (u8, u8)) {
+        let width = self.width as i32;
+        let height = if width > 0 { self.buffer.len() as i32 / width } else { 0 };
+        for (j, row) in tile.chunks(8).enumerate() {
+            let py = y + if flip_y { 7 - j as i32 } else { j as i32 };
+            if py < 0 || py >= height {
                 continue;
             }
-            buffer[offset + n] = *px;
-        }
-    } else {
-        for (n, px) in row.iter().enumerate() {
-            if *px == 0 {
-                continue;
+            for (i, px) in row.iter().enumerate() {
+                let px_x = x + if flip_x { 7 - i as i32 } else { i as i32 };
+                if *px == 0 || px_x < 0 || px_x >= width {
+                    continue;
+                }
+                let index = (py * width + px_x) as usize;
+                self.buffer[index] = palette << 4 | *px;
+                self.priorities[index] = priority;
             }
-            buffer[offset + n] = *px;
         }
     }
 }
 
-fn offset(width: u16, zx: u16, zy: u16, x: i16, y: i16) -> usize {
-    let mut center = (zy * width + zx) as usize;
-    if x >= 0 {
-        center += x as usize;
-    } else {
-        center -= (-x) as usize;
-    }
-    if y >= 0 {
-        center += y as usize * width as usize;
-    } else {
-        center -= (-y) as usize * width as usize;
-    }
-    center
-}
-
-pub fn paint_tile(buffer: &mut [u8], width: u16, (zx, zy): (u16, u16), tile: &Tile, (x, y): (i16, i16), flip_x: bool, flip_y: bool) {
-    let offset = offset(width, zx, zy, x, y);
-    _paint_tile(buffer, tile, width as usize, offset, flip_x, flip_y);
+fn position(zx: u16, zy: u16, x: i16, y: i16) -> (i32, i32) {
+    (zx as i32 + x as i32, zy as i32 + y as i32)
 }
 
-pub fn paint_block(buffer: &mut [u8], width: u16, (zx, zy): (u16, u16), (tile0, tile1, tile2, tile3): (&Tile, &Tile, &Tile, &Tile), (x, y): (i16, i16), flip_x: bool, flip_y: bool) {
-    let offset = offset(width, zx, zy, x, y);
-    let width = width as usize;
-    _paint_tile(buffer, tile0, width, offset, flip_x, flip_y);
-    _paint_tile(buffer, tile1, width, offset + 8, flip_x, flip_y);
-    _paint_tile(buffer, tile2, width, offset + width*8, flip_x, flip_y);
-    _paint_tile(buffer, tile3, width, offset + width*8 + 8, flip_x, flip_y);
+pub fn paint_outline(buffer: &mut [u8], width: u16, (zx, zy): (u16, u16), (left, top, right, bottom): (i16, i16, i16, i16), color: u8) {
+    let w = width as i32;
+    let h = if w > 0 { buffer.len() as i32 / w } else { 0 };
+    let mut put = |x: i16, y: i16| {
+        let (x, y) = position(zx, zy, x, y);
+        if x >= 0 && x < w && y >= 0 && y < h {
+            buffer[(y * w + x) as usize] = color;
+        }
+    };
+    for x in left..=right {
+        put(x, top);
+        put(x, bottom);
+    }
+    for y in top..=bottom {
+        put(left, y);
+        put(right, y);
+    }
 }
@@ -0,0 +1,117 @@
+// Afterimages: speed booster echoes, shinespark echoes and screw attack
+// trails are all just older frames drawn again where Samus used to be, with
+// their own palette. To keep everything in one indexed buffer, every echo
+// gets its own copy of the palette rows the frames use, and its pixels point
+// into those.
+
+use std::cmp;
+use frame_map::CompositedFrame;
+use util::palette_rows;
+
+/// Row 0 is the current frame's, which leaves 7 of the 8 palette rows
+pub const MAX_ECHOES: usize = 7;
+// Pixels are `row * 16 + color` in a u8
+const MAX_ROWS: usize = 16;
+
+pub struct Echo<'a> {
+    pub frame: &'a CompositedFrame,
+    /// Where this frame's origin goes, relative to the newest frame's origin
+    pub position: (i16, i16),
+    pub palette: &'a [u16],
+}
+
+/// Which palette rows `frames` draw with, in order
+pub fn rows_used<'a, I: IntoIterator<Item = &'a CompositedFrame>>(frames: I) -> Vec<u8> {
+    let mut used = [false; MAX_ROWS];
+    for frame in frames {
+        for &px in frame.buffer.iter().filter(|&&px| px & 0x0F != 0) {
+            used[(px >> 4) as usize] = true;
+        }
+    }
+    (0..MAX_ROWS as u8).filter(|&row| used[row as usize]).collect()
+}
+
+/// How many echoes fit when every layer needs `num_rows` palette rows
+pub fn max_echoes(num_rows: usize) -> usize {
+    cmp::min(MAX_ECHOES, MAX_ROWS / cmp::max(num_rows, 1) - 1)
+}
+
+/// Draws `echoes` (oldest first) under `current`, and returns the combined
+/// frame along with its palette: a copy of `rows` from `palette` first, then
+/// from each echo's palette, newest first. `rows` should be every row the
+/// frames draw with (see `rows_used`), the same for every frame of an
+/// animation so they all share the palette. Only the newest `max_echoes`
+/// echoes fit.
+pub fn trail(current: &CompositedFrame, palette: &[u16], echoes: &[Echo], rows: &[u8]) -> (CompositedFrame, Vec<u16>) {
+    let rows = if rows.is_empty() { &[0][..] } else { rows };
+    let echoes = &echoes[echoes.len().saturating_sub(max_echoes(rows.len()))..];
+    // Where each of a layer's rows ends up, counting from its first
+    let mut remap = [0u8; MAX_ROWS];
+    for (n, &row) in rows.iter().enumerate() {
+        remap[row as usize & 0x0F] = n as u8;
+    }
+    let layers: Vec<(&CompositedFrame, (i16, i16))> = echoes.iter()
+        .map(|echo| (echo.frame, echo.position))
+        .chain(Some((current, (0, 0))))
+        .collect();
+
+    // Bounds of every layer, relative to the newest frame's origin
+    let (left, top, right, bottom) = layers.iter().fold((0i16, 0i16, 0i16, 0i16), |(l, t, r, b), &(frame, (x, y))| {
+        (
+            cmp::min(l, x - frame.zero_x as i16),
+            cmp::min(t, y - frame.zero_y as i16),
+            cmp::max(r, x - frame.zero_x as i16 + frame.width as i16),
+            cmp::max(b, y - frame.zero_y as i16 + frame.height as i16),
+        )
+    });
+    let width = (right - left) as usize;
+    let height = (bottom - top) as usize;
+    let mut buffer = vec![0u8; width * height];
+    let mut priority = vec![0u8; width * height];
+
+    // Layers count down from the oldest echo, since the newest frame's rows
+    // come first
+    let num_echoes = echoes.len();
+    for (n, &(frame, (x, y))) in layers.iter().enumerate() {
+        let first_row = ((num_echoes - n) * rows.len()) as u8;
+        let frame_left = (x - frame.zero_x as i16 - left) as usize;
+        let frame_top = (y - frame.zero_y as i16 - top) as usize;
+        let lines = frame.buffer.chunks(frame.width as usize).zip(frame.priority.chunks(frame.width as usize));
+        for (j, (line, priorities)) in lines.enumerate() {
+            let start = (frame_top + j) * width + frame_left;
+            for (i, (&px, &p)) in line.iter().zip(priorities.iter()).enumerate() {
+                if px & 0x0F != 0 {
+                    buffer[start + i] = (first_row + remap[(px >> 4) as usize]) << 4 | (px & 0x0F);
+                    priority[start + i] = p;
+                }
+            }
+        }
+    }
+
+    let mut colors: Vec<u16> = copy_rows(palette, rows);
+    for echo in echoes.iter().rev() {
+        colors.extend(copy_rows(echo.palette, rows));
+    }
+
+    let frame = CompositedFrame {
+        buffer,
+        priority,
+        width: width as u16,
+        height: height as u16,
+        zero_x: (-left) as u16,
+        zero_y: (-top) as u16,
+        duration: current.duration,
+    };
+    (frame, colors)
+}
+
+// Single row palettes stand in for every row, like they do when drawn
+fn copy_rows(palette: &[u16], rows: &[u8]) -> Vec<u16> {
+    let colors = palette_rows(palette);
+    rows.iter()
+        .flat_map(|&row| {
+            let start = row as usize * 16;
+            (start..start + 16).map(|n| colors.get(n).cloned().unwrap_or(0)).collect::<Vec<_>>()
+        })
+        .collect()
+}
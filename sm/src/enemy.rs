@@ -3,120 +3,626 @@
 use snes::{Rom, SnesAddress};
 use std::{fmt};
 use byteorder::{ByteOrder, LittleEndian};
-use frame_map::{CompositedFrame, FrameMap};
+use frame_map::{CompositedFrame, FrameMap, Rect, SkippedPart};
+use instruction_list::{self, End, ENEMY_INSTRUCTIONS};
 use snes_bitplanes::{Bitplanes, Tile};
 
+// Every enemy header lives in bank $A0, 64 bytes apiece
+const ENEMY_HEADERS_START: SnesAddress = SnesAddress(0xA0CEBF);
+const ENEMY_HEADERS_END: SnesAddress = SnesAddress(0xA0F7FF);
+
+// Drop chance and vulnerability tables, pointed to from enemy headers
+const ENEMY_TABLES_BANK: u32 = 0xB40000;
+const DEFAULT_VULNERABILITIES: u16 = 0xEC1C;
+
+// Sprite palettes shared by all enemies: frozen enemies are drawn with
+// palette row 6, and enemies flash with row 7 when they get hurt
+const COMMON_SPRITE_PALETTES: SnesAddress = SnesAddress(0x9A8100);
+const FROZEN_PALETTE_ROW: usize = 6;
+const HURT_PALETTE_ROW: usize = 7;
+
+// Tiles for drops, explosions and the like, loaded at the start of OBJ VRAM
+// for everything to share
+const COMMON_SPRITE_TILES: SnesAddress = SnesAddress(0x9AD200);
+const NUM_COMMON_SPRITE_TILES: usize = 0x80;
+
+// Enemy tiles get loaded into the second page of OBJ VRAM, and their
+// spritemaps set the name table bit to point there
+const ENEMY_TILES_START: usize = 0x100;
+
+/// Reads every enemy header in the ROM, skipping slots that don't look
+/// like a real enemy.
+pub fn catalog<'a>(rom: &'a Rom) -> Vec<DNA<'a>> {
+    (ENEMY_HEADERS_START.0..ENEMY_HEADERS_END.0).step_by(64)
+        .map(|addr| DNA::read_from_rom(rom, SnesAddress(addr)))
+        .filter(DNA::is_valid)
+        .collect()
+}
+
+pub fn frozen_palette<'a>(rom: &'a Rom) -> &'a [u8] {
+    rom.read(COMMON_SPRITE_PALETTES.to_pc() + FROZEN_PALETTE_ROW * 32, 32)
+}
+
+pub fn common_sprite_tiles(rom: &Rom) -> Vec<Tile> {
+    Bitplanes::new(rom.read(COMMON_SPRITE_TILES.to_pc(), NUM_COMMON_SPRITE_TILES * 32)).collect()
+}
+
+/// `tiles` placed where an enemy's spritemaps look for them, on the page
+/// after `common`
+pub fn vram_tiles(common: &[Tile], tiles: Vec<Tile>) -> Vec<Tile> {
+    let mut vram = common.to_vec();
+    vram.resize(ENEMY_TILES_START, Tile([0; 64]));
+    vram.extend(tiles);
+    vram
+}
+
+pub fn hurt_palette<'a>(rom: &'a Rom) -> &'a [u8] {
+    rom.read(COMMON_SPRITE_PALETTES.to_pc() + HURT_PALETTE_ROW * 32, 32)
+}
+
 pub struct DNA<'a> {
+    address: SnesAddress,
     palet: u32,
     graphadr: u32,
     mb: u32,
     sizeb: u16,
+    health: u16,
+    damage: u16,
+    width: u16,
+    height: u16,
     piece: u16,
+    touch: u16,
+    shot: u16,
+    drops: u16,
+    vulnerabilities: u16,
     ename: u16,
+    format: SpritemapFormat,
     rom: &'a Rom<'a>,
 }
 
+/// Bosses and other large enemies use extended spritemaps: lists of whole
+/// spritemaps (each with a hitbox list) placed at offsets from the enemy.
+/// Which format an enemy uses is decided by its AI code, not its header.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SpritemapFormat {
+    Normal,
+    Extended,
+}
+
 impl<'a> DNA<'a> {
     pub fn read_from_rom(rom: &'a Rom, snes_addr: SnesAddress) -> Self {
         let addr = snes_addr.to_pc();
-        let dna = &rom.read(addr, 64);
+        let dna = rom.read(addr, 64);
 
         DNA {
+            address: snes_addr,
             sizeb: LittleEndian::read_u16(&dna[0..2]),
             palet: LittleEndian::read_u16(&dna[2..4]) as u32,
+            health: LittleEndian::read_u16(&dna[4..6]),
+            damage: LittleEndian::read_u16(&dna[6..8]),
+            width: LittleEndian::read_u16(&dna[8..10]),
+            height: LittleEndian::read_u16(&dna[10..12]),
             mb: (dna[12] as u32) << 16,
             piece: LittleEndian::read_u16(&dna[20..22]),
+            touch: LittleEndian::read_u16(&dna[48..50]),
+            shot: LittleEndian::read_u16(&dna[50..52]),
             graphadr: LittleEndian::read_u32(&dna[54..58]) & 0x00FFFFFF,
+            drops: LittleEndian::read_u16(&dna[58..60]),
+            vulnerabilities: LittleEndian::read_u16(&dna[60..62]),
             ename: LittleEndian::read_u16(&dna[62..64]),
-            rom: rom,
+            format: SpritemapFormat::Normal,
+            rom,
         }
     }
 
+    pub fn with_spritemap_format(mut self, format: SpritemapFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn address(&self) -> SnesAddress {
+        self.address
+    }
+
+    fn is_valid(&self) -> bool {
+        let bank = self.mb >> 16;
+        (0xA2..=0xB3).contains(&bank) && self.sizeb > 0 && self.sizeb.is_multiple_of(32)
+    }
+
     pub fn name(&self) -> Option<String> {
         let addr = SnesAddress((0x34 << 16) + self.ename as u32).to_pc();
         self.rom.read_string(addr, 16)
     }
 
+    pub fn health(&self) -> u16 {
+        self.health
+    }
+
+    /// Contact damage dealt to Samus
+    pub fn damage(&self) -> u16 {
+        self.damage
+    }
+
+    /// `None` for enemies that never drop anything
+    pub fn drop_chances(&self) -> Option<DropChances> {
+        if self.drops == 0 {
+            return None;
+        }
+        let addr = SnesAddress(ENEMY_TABLES_BANK + self.drops as u32).to_pc();
+        Some(DropChances::from_bytes(self.rom.read(addr, 6)))
+    }
+
+    /// How much damage each weapon does, and whether it can freeze the enemy.
+    /// Enemies without a table of their own use the game's default one.
+    /// Tables are 22 bytes long, but nothing reads the last one, so it's left
+    /// out.
+    pub fn vulnerabilities(&self) -> Vec<(Weapon, Vulnerability)> {
+        let pointer = if self.vulnerabilities == 0 { DEFAULT_VULNERABILITIES } else { self.vulnerabilities };
+        let addr = SnesAddress(ENEMY_TABLES_BANK + pointer as u32).to_pc();
+        WEAPONS.iter().cloned()
+            .zip(self.rom.read(addr, WEAPONS.len()).iter().cloned().map(Vulnerability::from_byte))
+            .collect()
+    }
+
+    /// Half the width of the enemy's hitbox
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Half the height of the enemy's hitbox
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
     pub fn palette(&self) -> &[u8] {
         let addr = SnesAddress(self.mb + self.palet).to_pc();
-        &self.rom.read(addr, 32)
-    }
-
-    fn frame_indices(&self) -> Vec<FrameIndex> {
-        let addr = SnesAddress(self.mb + self.palet).to_pc() + 0x20;
-        // Animations are followed by the ending bytes ED80 (littleendian)
-        // and the LE short address of the *start* of the animation
-        self.rom[addr..].chunks(4).take_while(|c| c[0..2] != [0xED, 0x80] && c[2..4] != [0xED, 0x80])
-            .map(|slice| {
-                let duration = LittleEndian::read_u16(&slice[0..2]);
-                let addr = LittleEndian::read_u16(&slice[2..4]);
-                FrameIndex {
-                    duration: duration,
-                    snes_addr: addr,
-                }
-            })
+        self.rom.read(addr, 32)
+    }
+
+    /// Palettes of the other enemies drawn with the same graphics, e.g. the
+    /// differently colored Space Pirates, named after those enemies. Looks
+    /// through `catalog`, so build that once and share it.
+    pub fn alternative_palettes(&self, catalog: &[DNA]) -> Vec<(String, &'a [u8])> {
+        let mut palettes: Vec<(String, &'a [u8])> = Vec::new();
+        for other in catalog {
+            if other.graphadr != self.graphadr || other.mb + other.palet == self.mb + self.palet {
+                continue;
+            }
+            let addr = SnesAddress(other.mb + other.palet).to_pc();
+            let palette = self.rom.read(addr, 32);
+            if palettes.iter().any(|&(_, p)| p == palette) {
+                continue;
+            }
+            let name = other.name().unwrap_or_else(|| format!("{:06X}", other.address.0));
+            palettes.push((name, palette));
+        }
+        palettes
+    }
+
+    fn instruction_lists_start(&self) -> SnesAddress {
+        // The first instruction list usually sits right after the palette
+        SnesAddress(self.mb + self.palet + 0x20)
+    }
+
+    /// Every instruction list found packed after the palette, in ROM order.
+    pub fn animations(&self) -> Vec<Animation> {
+        instruction_list::scan(self.rom, self.instruction_lists_start(), ENEMY_INSTRUCTIONS).into_iter()
+            .map(|addr| self.animation(addr))
             .collect()
     }
 
-    pub fn frames(&self) -> Vec<Frame> {
-        let indices = self.frame_indices();
-        indices.into_iter()
-            .map(|fi| {
-                let full_addr = SnesAddress(self.mb + fi.snes_addr as u32);
+    /// The animation for the instruction list at `snes_addr`, e.g. one
+    /// that an enemy's AI code points to directly.
+    pub fn animation(&self, snes_addr: SnesAddress) -> Animation {
+        let sequence = instruction_list::sequence(self.rom, snes_addr, ENEMY_INSTRUCTIONS);
+        let frames = sequence.frames.into_iter()
+            .map(|(duration, spritemap)| self.frame(duration, spritemap))
+            .collect();
+        Animation {
+            name: format!("{:02X}:{:04X}", snes_addr.0 >> 16, snes_addr.0 & 0xFFFF),
+            address: snes_addr,
+            frames,
+            end: sequence.end,
+        }
+    }
+
+    fn frame(&self, duration: u16, spritemap: u16) -> Frame {
+        let full_addr = SnesAddress(self.mb + spritemap as u32);
+        match self.format {
+            SpritemapFormat::Normal => Frame {
+                duration,
+                parts: FrameMap::from_rom(self.rom, full_addr, 0),
+                extended: Vec::new(),
+                hitboxes: vec![Hitbox {
+                    left: -(self.width as i16),
+                    top: -(self.height as i16),
+                    right: self.width as i16,
+                    bottom: self.height as i16,
+                    touch: self.touch,
+                    shot: self.shot,
+                }],
+            },
+            SpritemapFormat::Extended => {
+                let extended = ExtendedPart::from_rom(self.rom, full_addr);
+                // Anything pointing below $8000 isn't in the ROM, it's what
+                // you get reading something that isn't an extended spritemap
+                let parts = extended.iter()
+                    .filter(|e| e.spritemap >= 0x8000)
+                    .flat_map(|e| {
+                        let addr = SnesAddress(self.mb + e.spritemap as u32);
+                        FrameMap::from_rom(self.rom, addr, 0).into_iter()
+                            .map(move |part| part.translated(e.x, e.y))
+                    })
+                    .collect();
+                let hitboxes = extended.iter()
+                    .filter(|e| e.hitbox >= 0x8000)
+                    .flat_map(|e| {
+                        let addr = SnesAddress(self.mb + e.hitbox as u32);
+                        Hitbox::from_rom(self.rom, addr).into_iter()
+                            .map(move |hitbox| hitbox.translated(e.x, e.y))
+                    })
+                    .collect();
                 Frame {
-                    duration: fi.duration,
-                    parts: FrameMap::from_rom(self.rom, full_addr, 0),
+                    duration,
+                    parts,
+                    extended,
+                    hitboxes,
                 }
-            })
-            .collect()
+            },
+        }
+    }
+
+    pub fn frames(&self) -> Vec<Frame> {
+        self.animation(self.instruction_lists_start()).frames
     }
 
     pub fn graphics(&self) -> Vec<Tile> {
         let addr = SnesAddress(self.graphadr).to_pc();
-        let data = &self.rom.read(addr, self.sizeb as usize);
+        let data = self.rom.read(addr, self.sizeb as usize);
         Bitplanes::new(data).collect()
     }
+
+    /// The graphics laid out the way `Frame::composited` wants them
+    pub fn vram_graphics(&self) -> Vec<Tile> {
+        vram_tiles(&common_sprite_tiles(self.rom), self.graphics())
+    }
 }
 
 impl<'a> fmt::Debug for DNA<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f,
-            "DNA {{ sizeb: {:04X}, palet: {:04X}, mb: {:02X}, piece: {:04X}, graphadr: {:06X}, ename: {:02X} }}",
-            self.sizeb, self.palet, self.mb, self.piece, self.graphadr, self.ename
+            "DNA {{ address: {:06X}, sizeb: {:04X}, palet: {:04X}, health: {}, damage: {}, width: {}, height: {}, mb: {:02X}, piece: {:04X}, graphadr: {:06X}, ename: {:02X} }}",
+            self.address.0, self.sizeb, self.palet, self.health, self.damage, self.width, self.height, self.mb, self.piece, self.graphadr, self.ename
         )
     }
 }
 
-struct FrameIndex {
-    duration: u16,
-    snes_addr: u16,
+pub struct Animation {
+    pub name: String,
+    pub address: SnesAddress,
+    pub frames: Vec<Frame>,
+    pub end: End,
 }
 
-impl fmt::Debug for FrameIndex {
+impl fmt::Debug for Animation {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f,
-            "FrameIndex {{ duration: {:02X}, snes_addr: {:02X} }}",
-            self.duration, self.snes_addr
+            "Animation {{ name: {}, frames: {}, end: {:?} }}",
+            self.name, self.frames.len(), self.end
+        )
+    }
+}
+
+/// Chances out of 255 of each pickup dropping when the enemy dies
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DropChances {
+    pub small_energy: u8,
+    pub large_energy: u8,
+    pub missile: u8,
+    pub nothing: u8,
+    pub super_missile: u8,
+    pub power_bomb: u8,
+}
+
+impl DropChances {
+    fn from_bytes(table: &[u8]) -> Self {
+        DropChances {
+            small_energy: table[0],
+            large_energy: table[1],
+            missile: table[2],
+            nothing: table[3],
+            super_missile: table[4],
+            power_bomb: table[5],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Weapon {
+    Power,
+    Wave,
+    Ice,
+    IceWave,
+    Spazer,
+    WaveSpazer,
+    IceSpazer,
+    WaveIceSpazer,
+    Plasma,
+    WavePlasma,
+    IcePlasma,
+    WaveIcePlasma,
+    Missile,
+    SuperMissile,
+    Bomb,
+    PowerBomb,
+    SpeedBooster,
+    Shinespark,
+    ScrewAttack,
+    HyperBeam,
+    PseudoScrewAttack,
+}
+
+/// In the same order as the bytes of a vulnerability table
+pub const WEAPONS: [Weapon; 21] = [
+    Weapon::Power, Weapon::Wave, Weapon::Ice, Weapon::IceWave,
+    Weapon::Spazer, Weapon::WaveSpazer, Weapon::IceSpazer, Weapon::WaveIceSpazer,
+    Weapon::Plasma, Weapon::WavePlasma, Weapon::IcePlasma, Weapon::WaveIcePlasma,
+    Weapon::Missile, Weapon::SuperMissile, Weapon::Bomb, Weapon::PowerBomb,
+    Weapon::SpeedBooster, Weapon::Shinespark, Weapon::ScrewAttack, Weapon::HyperBeam,
+    Weapon::PseudoScrewAttack,
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Vulnerability {
+    /// Damage multiplier in halves: 0 is immune, 2 is normal damage
+    pub multiplier: u8,
+    pub freezable: bool,
+}
+
+impl Vulnerability {
+    fn from_byte(byte: u8) -> Self {
+        // FF is a special case: the weapon has no effect at all
+        if byte == 0xFF {
+            return Vulnerability { multiplier: 0, freezable: false };
+        }
+        Vulnerability {
+            multiplier: byte & 0x0F,
+            freezable: byte & 0x80 == 0,
+        }
+    }
+
+    pub fn damage_multiplier(&self) -> f32 {
+        self.multiplier as f32 / 2.0
+    }
+}
+
+/// One entry of an extended spritemap
+#[derive(Copy, Clone)]
+pub struct ExtendedPart {
+    pub x: i16,
+    pub y: i16,
+    pub spritemap: u16,
+    pub hitbox: u16,
+}
+
+impl ExtendedPart {
+    pub fn from_rom(rom: &Rom, snes_addr: SnesAddress) -> Vec<Self> {
+        let addr = snes_addr.to_pc();
+        rom.read_counted(addr, 8)
+            .chunks(8)
+            .map(|slice| ExtendedPart {
+                x: LittleEndian::read_i16(&slice[0..2]),
+                y: LittleEndian::read_i16(&slice[2..4]),
+                spritemap: LittleEndian::read_u16(&slice[4..6]),
+                hitbox: LittleEndian::read_u16(&slice[6..8]),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Debug for ExtendedPart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+            "ExtendedPart {{ x: {}, y: {}, spritemap: {:04X}, hitbox: {:04X} }}",
+            self.x, self.y, self.spritemap, self.hitbox
+        )
+    }
+}
+
+/// A collision rectangle relative to the enemy's position, along with the
+/// routines that run when Samus touches it or a projectile hits it
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Hitbox {
+    pub left: i16,
+    pub top: i16,
+    pub right: i16,
+    pub bottom: i16,
+    pub touch: u16,
+    pub shot: u16,
+}
+
+impl Hitbox {
+    pub fn from_rom(rom: &Rom, snes_addr: SnesAddress) -> Vec<Self> {
+        let addr = snes_addr.to_pc();
+        rom.read_counted(addr, 12)
+            .chunks(12)
+            .map(|slice| Hitbox {
+                left: LittleEndian::read_i16(&slice[0..2]),
+                top: LittleEndian::read_i16(&slice[2..4]),
+                right: LittleEndian::read_i16(&slice[4..6]),
+                bottom: LittleEndian::read_i16(&slice[6..8]),
+                touch: LittleEndian::read_u16(&slice[8..10]),
+                shot: LittleEndian::read_u16(&slice[10..12]),
+            })
+            .collect()
+    }
+
+    pub fn translated(&self, dx: i16, dy: i16) -> Self {
+        Hitbox {
+            left: self.left.wrapping_add(dx),
+            top: self.top.wrapping_add(dy),
+            right: self.right.wrapping_add(dx),
+            bottom: self.bottom.wrapping_add(dy),
+            ..*self
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        (self.left, self.top, self.right, self.bottom)
+    }
+}
+
+impl fmt::Debug for Hitbox {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+            "Hitbox {{ ({}, {}) - ({}, {}), touch: {:04X}, shot: {:04X} }}",
+            self.left, self.top, self.right, self.bottom, self.touch, self.shot
         )
     }
 }
 
 pub struct Frame {
     parts: Vec<FrameMap>,
+    extended: Vec<ExtendedPart>,
+    hitboxes: Vec<Hitbox>,
     duration: u16,
 }
 
 impl fmt::Debug for Frame {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f,
-            "Frame {{ duration: {:02X}, parts: {:?} }}",
-            self.duration, self.parts
+            "Frame {{ duration: {:02X}, parts: {:?}, extended: {:?}, hitboxes: {:?} }}",
+            self.duration, self.parts, self.extended, self.hitboxes
         )
     }
 }
 
 impl Frame {
+    pub fn new(parts: Vec<FrameMap>, hitboxes: Vec<Hitbox>, duration: u16) -> Self {
+        Frame {
+            parts,
+            extended: Vec::new(),
+            hitboxes,
+            duration,
+        }
+    }
+
+    /// The sub-spritemaps this frame was built from, if it came from an
+    /// extended spritemap.
+    pub fn extended_parts(&self) -> &[ExtendedPart] {
+        &self.extended
+    }
+
+    pub fn hitboxes(&self) -> &[Hitbox] {
+        &self.hitboxes
+    }
+
+    /// `tiles` is all of OBJ VRAM, see `vram_tiles`
     pub fn composited(&self, tiles: &[Tile]) -> CompositedFrame {
-        FrameMap::composite(&self.parts, tiles, self.duration, 0)
+        self.composited_with_diagnostics(tiles, None).0
+    }
+
+    /// The composited frame with its hitboxes outlined in palette index `color`
+    pub fn composited_with_hitboxes(&self, tiles: &[Tile], color: u8) -> CompositedFrame {
+        self.composited_with_diagnostics(tiles, Some(color)).0
+    }
+
+    /// Like `composited`, outlining the hitboxes if there's a `hitbox_color`,
+    /// and also returning whichever parts couldn't be drawn
+    pub fn composited_with_diagnostics(&self, tiles: &[Tile], hitbox_color: Option<u8>) -> (CompositedFrame, Vec<SkippedPart>) {
+        let rects: Vec<_> = match hitbox_color {
+            Some(_) => self.hitboxes.iter().map(Hitbox::rect).collect(),
+            None => Vec::new(),
+        };
+        FrameMap::composite_with_diagnostics(&self.parts, tiles, &rects, hitbox_color.unwrap_or(0), self.duration, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{vram_tiles, DropChances, Frame, DNA, SpritemapFormat, Vulnerability};
+    use frame_map::FrameMap;
+    use snes::{Rom, SnesAddress};
+    use snes_bitplanes::Tile;
+
+    #[test]
+    fn garbage_extended_spritemaps_stop_at_the_end_of_the_rom() {
+        let mut data = vec![0u8; 0x40];
+        // Spritemaps live in bank $80
+        data[12] = 0x80;
+        // $80:8040, one part at the origin
+        data.extend_from_slice(&[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        // $80:8048, one hitbox
+        data.extend_from_slice(&[0x01, 0x00, 0xFC, 0xFF, 0xFC, 0xFF, 0x04, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        // $80:8056, claims far more parts than there's room for. The first
+        // is nearly out of range, the second points at nothing.
+        data.extend_from_slice(&[0xFF, 0xFF]);
+        data.extend_from_slice(&[0xFF, 0x7F, 0x00, 0x00, 0x40, 0x80, 0x48, 0x80]);
+        data.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00]);
+        data.extend_from_slice(&[0xFF, 0xFF, 0xFF]);
+        let rom = Rom(&data);
+
+        let dna = DNA::read_from_rom(&rom, SnesAddress(0x808000)).with_spritemap_format(SpritemapFormat::Extended);
+        let frame = dna.frame(1, 0x8056);
+        assert_eq!(frame.extended_parts().len(), 2);
+        assert_eq!(frame.parts.len(), 1);
+        assert_eq!(frame.parts[0].x(), 0x7FFF);
+        assert_eq!(frame.hitboxes().len(), 1);
+        assert_eq!(frame.hitboxes()[0].left, 0x7FFB);
+        assert_eq!(frame.hitboxes()[0].right, i16::MIN + 3);
+
+        // Counts cut off by the end of the ROM, and pointers past it
+        assert!(FrameMap::from_rom(&rom, SnesAddress(0x808069), 0).is_empty());
+        assert!(FrameMap::from_rom(&rom, SnesAddress(0x80FFF0), 0).is_empty());
+    }
+
+    #[test]
+    fn vulnerability_bytes() {
+        let cases = [
+            // Low nibble is the multiplier, high bit stops freezing
+            (0x02, 2, true),
+            (0x82, 2, false),
+            (0x0F, 15, true),
+            (0x00, 0, true),
+            (0x80, 0, false),
+            // Nothing else in the high nibble matters
+            (0x74, 4, true),
+            (0xF4, 4, false),
+            // Except FF, which makes the weapon do nothing at all
+            (0xFF, 0, false),
+        ];
+        for &(byte, multiplier, freezable) in cases.iter() {
+            assert_eq!(Vulnerability::from_byte(byte), Vulnerability { multiplier, freezable }, "{:02X}", byte);
+        }
+        assert_eq!(Vulnerability::from_byte(0x01).damage_multiplier(), 0.5);
+    }
+
+    #[test]
+    fn drop_chances() {
+        let chances = DropChances::from_bytes(&[0x3C, 0x3C, 0x3C, 0x50, 0x00, 0x0F]);
+        assert_eq!(chances, DropChances {
+            small_energy: 0x3C,
+            large_energy: 0x3C,
+            missile: 0x3C,
+            nothing: 0x50,
+            super_missile: 0x00,
+            power_bomb: 0x0F,
+        });
+
+        // A null pointer means the enemy never drops anything
+        let data = vec![0u8; 0x40];
+        let rom = Rom(&data);
+        assert_eq!(DNA::read_from_rom(&rom, SnesAddress(0x808000)).drop_chances(), None);
+    }
+
+    #[test]
+    fn spritemaps_use_the_second_page() {
+        let mut tiles = vec![Tile([0; 64]); 0x10];
+        tiles[0x05] = Tile([9; 64]);
+        // Priority 2, palette 0, name table bit set
+        let part = FrameMap::from_slice(&[0x00, 0x00, 0x00, 0x05, 0x21]);
+        let frame = Frame::new(vec![part], Vec::new(), 1).composited(&vram_tiles(&[], tiles));
+        assert_eq!(frame.buffer[0], 9);
+        assert_eq!(frame.priority[0], 2);
     }
 }
@@ -0,0 +1,337 @@
+// Indexed PNGs, pixel for pixel. The PLTE chunk is the SNES palette (all 8
+// rows, same as the GIFs) and index 0 is transparent through tRNS.
+//
+// Animations go out as APNG, or as animated WebP with the `webp` feature.
+// Unlike GIF's hundredths of a second, APNG delays are fractions, so every
+// frame lasts exactly its number of ticks over 60 (or 50 on a PAL console).
+
+use std::io::{self, Write};
+use png::{BitDepth, BlendOp, ColorType, DisposeOp, Encoder};
+use snes_bitplanes::Tile;
+use frame_map::CompositedFrame;
+use sprite::Sprite;
+use util::{palette_rows, ColorConversion};
+
+const TILES_PER_ROW: usize = 16;
+
+/// What the game's frame counter ticks at
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrameRate {
+    Ntsc,
+    Pal,
+}
+
+impl FrameRate {
+    pub fn hz(&self) -> u16 {
+        match *self {
+            FrameRate::Ntsc => 60,
+            FrameRate::Pal => 50,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Apng,
+    #[cfg(feature = "webp")]
+    WebP,
+}
+
+/// Writes every frame of the sprite on its shared canvas, looping forever
+pub fn write_animation<W: Write>(w: W, sprite: &Sprite, format: AnimationFormat, rate: FrameRate) -> io::Result<()> {
+    match format {
+        AnimationFormat::Apng => apng(w, sprite, rate),
+        #[cfg(feature = "webp")]
+        AnimationFormat::WebP => webp::animated_webp(w, sprite, rate),
+    }
+}
+
+/// Writes an indexed PNG of `width` x `height` pixels
+pub fn write_png<W: Write>(w: W, width: u32, height: u32, pixels: &[u8], palette: &[u16], conversion: ColorConversion) -> io::Result<()> {
+    let mut encoder = Encoder::new(w, width, height);
+    encoder.set_color(ColorType::Indexed);
+    encoder.set_depth(BitDepth::Eight);
+    encoder.set_palette(plte(palette, conversion));
+    encoder.set_trns(vec![0u8]);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    Ok(())
+}
+
+/// Indexed like the still PNGs, one full canvas frame after another, each
+/// replacing the last instead of being drawn over it
+pub fn apng<W: Write>(w: W, sprite: &Sprite, rate: FrameRate) -> io::Result<()> {
+    check_not_empty(sprite)?;
+    let frames = sprite.canvas_frames();
+    let mut encoder = Encoder::new(w, sprite.width() as u32, sprite.height() as u32);
+    encoder.set_color(ColorType::Indexed);
+    encoder.set_depth(BitDepth::Eight);
+    encoder.set_palette(plte(sprite.palette(), sprite.color_conversion()));
+    encoder.set_trns(vec![0u8]);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    encoder.set_blend_op(BlendOp::Source)?;
+    encoder.set_dispose_op(DisposeOp::None)?;
+    let mut writer = encoder.write_header()?;
+    for frame in frames.iter() {
+        writer.set_frame_delay(frame.duration, rate.hz())?;
+        writer.write_image_data(&frame.buffer)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+// Nothing to make an image out of, and zero sized images aren't allowed
+fn check_not_empty(sprite: &Sprite) -> io::Result<()> {
+    if sprite.frames().is_empty() || sprite.width() == 0 || sprite.height() == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the sprite is empty"));
+    }
+    Ok(())
+}
+
+fn plte(palette: &[u16], conversion: ColorConversion) -> Vec<u8> {
+    palette_rows(palette).iter()
+        .map(|c| conversion.rgb888(c))
+        .fold(Vec::new(), |mut v, (r, g, b)| {
+            v.push(r); v.push(g); v.push(b);
+            v
+        })
+}
+
+/// One frame, cropped to its own size
+pub fn frame_png<W: Write>(w: W, frame: &CompositedFrame, palette: &[u16], conversion: ColorConversion) -> io::Result<()> {
+    write_png(w, frame.width as u32, frame.height as u32, &frame.buffer, palette, conversion)
+}
+
+/// Where each frame sits in a sprite sheet: a grid of cells the size of the
+/// sprite's shared canvas, as close to square as it gets
+pub struct SheetLayout {
+    pub columns: usize,
+    pub rows: usize,
+    pub cell_width: usize,
+    pub cell_height: usize,
+}
+
+impl SheetLayout {
+    pub fn new(sprite: &Sprite) -> Self {
+        let count = sprite.frames().len();
+        let mut columns = 1;
+        while columns * columns < count {
+            columns += 1;
+        }
+        SheetLayout {
+            columns,
+            rows: count.div_ceil(columns),
+            cell_width: sprite.width() as usize,
+            cell_height: sprite.height() as usize,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.columns * self.cell_width
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows * self.cell_height
+    }
+
+    /// Top left corner of frame `n`
+    pub fn position(&self, n: usize) -> (usize, usize) {
+        ((n % self.columns) * self.cell_width, (n / self.columns) * self.cell_height)
+    }
+}
+
+/// Every frame of the sprite on one sheet, all lined up on the same origin
+pub fn sprite_sheet_png<W: Write>(w: W, sprite: &Sprite) -> io::Result<()> {
+    check_not_empty(sprite)?;
+    let layout = SheetLayout::new(sprite);
+    let width = layout.width();
+    let mut pixels = vec![0u8; width * layout.height()];
+    for (n, frame) in sprite.canvas_frames().iter().enumerate() {
+        let (x, y) = layout.position(n);
+        for (j, line) in frame.buffer.chunks(layout.cell_width).enumerate() {
+            let start = (y + j) * width + x;
+            pixels[start..start + line.len()].copy_from_slice(line);
+        }
+    }
+    write_png(w, width as u32, layout.height() as u32, &pixels, sprite.palette(), sprite.color_conversion())
+}
+
+/// Raw tiles, 16 to a row, the way the viewer's sprite sheet mode shows them
+pub fn tile_sheet_png<W: Write>(w: W, tiles: &[Tile], palette: &[u16], conversion: ColorConversion) -> io::Result<()> {
+    if tiles.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "there are no tiles"));
+    }
+    let width = TILES_PER_ROW * 8;
+    let rows = tiles.len().div_ceil(TILES_PER_ROW);
+    let mut pixels = vec![0u8; width * rows * 8];
+    for (i, tile) in tiles.iter().enumerate() {
+        let (tile_x, tile_y) = (i % TILES_PER_ROW, i / TILES_PER_ROW);
+        for (j, index) in tile.iter().enumerate() {
+            let (x, y) = (tile_x * 8 + j % 8, tile_y * 8 + j / 8);
+            pixels[y * width + x] = *index;
+        }
+    }
+    write_png(w, width as u32, (rows * 8) as u32, &pixels, palette, conversion)
+}
+
+// WebP has no indexed color, so frames go out as lossless RGBA. Each one gets
+// encoded as a still image, and its VP8L chunk moved into an ANMF frame of
+// an animated file. Durations are whole milliseconds, so they're rounded
+// from when each frame starts rather than one by one, to keep the animation
+// from drifting.
+#[cfg(feature = "webp")]
+mod webp {
+    use std::io::{self, Write};
+    use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+    use image_webp::{ColorType, WebPEncoder};
+    use sprite::Sprite;
+    use super::{check_not_empty, FrameRate};
+
+    const ANIMATION: u8 = 0x02;
+    const ALPHA: u8 = 0x10;
+    // Frames replace the canvas instead of blending with it
+    const NO_BLEND: u8 = 0x02;
+    // Skip RIFF, size, WEBP, VP8L and the chunk's size
+    const VP8L_OFFSET: usize = 20;
+
+    pub fn animated_webp<W: Write>(mut w: W, sprite: &Sprite, rate: FrameRate) -> io::Result<()> {
+        check_not_empty(sprite)?;
+        let (width, height) = (sprite.width() as u32, sprite.height() as u32);
+        let colors = sprite.palette888();
+        let hz = rate.hz() as u64;
+
+        let mut chunks = Vec::new();
+        let mut vp8x = vec![ANIMATION | ALPHA, 0, 0, 0];
+        vp8x.write_u24::<LittleEndian>(width - 1)?;
+        vp8x.write_u24::<LittleEndian>(height - 1)?;
+        write_chunk(&mut chunks, b"VP8X", &vp8x)?;
+        write_chunk(&mut chunks, b"ANIM", &[0, 0, 0, 0, 0, 0])?; // transparent background, loop forever
+
+        let mut ticks = 0u64;
+        for frame in sprite.canvas_frames() {
+            let mut rgba = Vec::with_capacity(frame.buffer.len() * 4);
+            for &px in frame.buffer.iter() {
+                let (r, g, b) = colors[px as usize];
+                rgba.extend_from_slice(&[r, g, b, if px == 0 { 0 } else { 0xFF }]);
+            }
+            let mut still = Vec::new();
+            WebPEncoder::new(&mut still).encode(&rgba, width, height, ColorType::Rgba8)
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            let size = LittleEndian::read_u32(&still[VP8L_OFFSET - 4..VP8L_OFFSET]) as usize;
+
+            let start = (ticks * 1000 + hz / 2) / hz;
+            ticks += frame.duration as u64;
+            let end = (ticks * 1000 + hz / 2) / hz;
+
+            let mut anmf = Vec::new();
+            anmf.write_u24::<LittleEndian>(0)?; // x and y
+            anmf.write_u24::<LittleEndian>(0)?;
+            anmf.write_u24::<LittleEndian>(width - 1)?;
+            anmf.write_u24::<LittleEndian>(height - 1)?;
+            anmf.write_u24::<LittleEndian>((end - start) as u32)?;
+            anmf.write_u8(NO_BLEND)?;
+            write_chunk(&mut anmf, b"VP8L", &still[VP8L_OFFSET..VP8L_OFFSET + size])?;
+            write_chunk(&mut chunks, b"ANMF", &anmf)?;
+        }
+
+        w.write_all(b"RIFF")?;
+        w.write_u32::<LittleEndian>(chunks.len() as u32 + 4)?;
+        w.write_all(b"WEBP")?;
+        w.write_all(&chunks)
+    }
+
+    fn write_chunk(w: &mut Vec<u8>, name: &[u8; 4], data: &[u8]) -> io::Result<()> {
+        w.write_all(name)?;
+        w.write_u32::<LittleEndian>(data.len() as u32)?;
+        w.write_all(data)?;
+        if data.len() % 2 == 1 {
+            w.write_u8(0)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use frame_map::CompositedFrame;
+    use sprite::Sprite;
+    use util::ColorConversion;
+    use super::{tile_sheet_png, write_animation, AnimationFormat, FrameRate};
+
+    #[test]
+    fn apng_delays_are_exact() {
+        let palette = [0u16; 16];
+        // Only the middle pixel of each frame is opaque
+        let frames = [5, 7, 1].iter().map(|&d| CompositedFrame::test_pattern(3, 3, d)).collect();
+        let sprite = Sprite::new(frames, &palette);
+        let mut file = Vec::new();
+        write_animation(&mut file, &sprite, AnimationFormat::Apng, FrameRate::Pal).unwrap();
+
+        let mut reader = ::png::Decoder::new(Cursor::new(file)).read_info().unwrap();
+        assert_eq!(reader.info().animation_control.map(|a| a.num_frames), Some(3));
+        let mut buf = vec![0; reader.output_buffer_size()];
+        for &duration in [5, 7, 1].iter() {
+            reader.next_frame(&mut buf).unwrap();
+            let control = reader.info().frame_control.unwrap();
+            assert_eq!((control.delay_num, control.delay_den), (duration, 50));
+            assert_eq!(&buf[..9], &[0, 0, 0, 0, 2, 0, 0, 0, 0]);
+        }
+    }
+
+    #[test]
+    fn no_tiles_is_an_error() {
+        let err = tile_sheet_png(Vec::new(), &[], &[0; 16], ColorConversion::default()).err().unwrap();
+        assert_eq!(err.kind(), ::std::io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(feature = "webp")]
+    #[test]
+    fn webp_durations_dont_drift() {
+        let palette = [0u16; 16];
+        // Only the middle pixel of each frame is opaque
+        let frames = [5, 7, 1].iter().map(|&d| CompositedFrame::test_pattern(3, 3, d)).collect();
+        let sprite = Sprite::new(frames, &palette);
+        let mut file = Vec::new();
+        write_animation(&mut file, &sprite, AnimationFormat::WebP, FrameRate::Ntsc).unwrap();
+
+        let mut decoder = ::image_webp::WebPDecoder::new(Cursor::new(file)).unwrap();
+        assert!(decoder.is_animated());
+        assert_eq!(decoder.num_frames(), 3);
+        // 13 ticks, rounded once
+        assert_eq!(decoder.loop_duration(), 217);
+        let mut rgba = vec![0; decoder.output_buffer_size().unwrap()];
+        decoder.read_frame(&mut rgba).unwrap();
+        assert_eq!((rgba[3], rgba[4 * 4 + 3]), (0, 0xFF));
+    }
+
+    #[cfg(feature = "webp")]
+    #[test]
+    fn webp_frames_round_trip() {
+        let palette: Vec<u16> = (0..16).map(|n| n * 0x0421).collect();
+        let frames = (1..4u8).map(|n| {
+            let mut frame = CompositedFrame::test_pattern(3, 2, n as u16);
+            frame.buffer = vec![0, n, n + 3, n + 6, 0, 15];
+            frame
+        }).collect();
+        let sprite = Sprite::new(frames, &palette);
+        let colors = sprite.palette888();
+        let mut file = Vec::new();
+        write_animation(&mut file, &sprite, AnimationFormat::WebP, FrameRate::Ntsc).unwrap();
+
+        let mut decoder = ::image_webp::WebPDecoder::new(Cursor::new(file)).unwrap();
+        assert_eq!(decoder.dimensions(), (sprite.width() as u32, sprite.height() as u32));
+        let mut rgba = vec![0; decoder.output_buffer_size().unwrap()];
+        for frame in sprite.canvas_frames() {
+            decoder.read_frame(&mut rgba).unwrap();
+            for (&px, out) in frame.buffer.iter().zip(rgba.chunks(4)) {
+                if px == 0 {
+                    assert_eq!(out[3], 0);
+                } else {
+                    let (r, g, b) = colors[px as usize];
+                    assert_eq!(out, &[r, g, b, 0xFF][..]);
+                }
+            }
+        }
+    }
+}
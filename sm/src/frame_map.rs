@@ -1,11 +1,14 @@
 use snes::{Rom, SnesAddress};
 use snes_bitplanes::Tile;
-use std::{fmt, mem};
-use byteorder::{ByteOrder, LittleEndian};
-use centered_canvas;
+use std::{cmp, fmt, mem};
+use centered_canvas::{self, Canvas};
 
+#[derive(Clone)]
 pub struct CompositedFrame {
+    /// Palette row in the high nibble, color in the low one
     pub buffer: Vec<u8>,
+    /// OBJ priority (0-3) of whichever part drew each pixel
+    pub priority: Vec<u8>,
     pub width: u16,
     pub height: u16,
     pub zero_x: u16,
@@ -13,11 +16,98 @@ pub struct CompositedFrame {
     pub duration: u16,
 }
 
+impl CompositedFrame {
+    /// The same frame, moved onto a `width` x `height` canvas so that its
+    /// origin lands on `(zero_x, zero_y)`. Whatever doesn't fit is cut off.
+    pub fn on_canvas(&self, width: u16, height: u16, (zero_x, zero_y): (u16, u16)) -> CompositedFrame {
+        let size = width as usize * height as usize;
+        let mut buffer = vec![0; size];
+        let mut priority = vec![0; size];
+        let dx = zero_x as i32 - self.zero_x as i32;
+        let dy = zero_y as i32 - self.zero_y as i32;
+        for y in 0..self.height as i32 {
+            let to_y = y + dy;
+            if to_y < 0 || to_y >= height as i32 {
+                continue;
+            }
+            for x in 0..self.width as i32 {
+                let to_x = x + dx;
+                if to_x < 0 || to_x >= width as i32 {
+                    continue;
+                }
+                let from = (y * self.width as i32 + x) as usize;
+                let to = (to_y * width as i32 + to_x) as usize;
+                buffer[to] = self.buffer[from];
+                priority[to] = self.priority[from];
+            }
+        }
+        CompositedFrame {
+            buffer,
+            priority,
+            width,
+            height,
+            zero_x,
+            zero_y,
+            duration: self.duration,
+        }
+    }
+}
+
+#[cfg(test)]
+impl CompositedFrame {
+    /// A `width` x `height` frame with its origin in the middle, opaque
+    /// except for a one pixel transparent border
+    pub fn test_pattern(width: u16, height: u16, duration: u16) -> CompositedFrame {
+        let (w, h) = (width as usize, height as usize);
+        let buffer = (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                if x == 0 || y == 0 || x == w - 1 || y == h - 1 { 0 } else { 1 + (x % 15) as u8 }
+            })
+            .collect::<Vec<u8>>();
+        CompositedFrame {
+            priority: vec![0; buffer.len()],
+            buffer,
+            width,
+            height,
+            zero_x: width / 2,
+            zero_y: height / 2,
+            duration,
+        }
+    }
+}
+
+/// A part that `composite` had to leave out, by its index in the frame map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedPart {
+    pub index: usize,
+    pub reason: SkipReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The part wants a tile past the end of the tiles we were given
+    MissingTile { tile: usize, available: usize },
+}
+
+impl fmt::Display for SkippedPart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.reason {
+            SkipReason::MissingTile { tile, available } =>
+                write!(f, "part {} wants tile {:#X} but there are only {:#X}", self.index, tile, available),
+        }
+    }
+}
+
+/// An inclusive `(left, top, right, bottom)` rectangle relative to a
+/// sprite's origin
+pub type Rect = (i16, i16, i16, i16);
+
 #[derive(Clone)]
 pub struct FrameMap {
-    x: i8,
+    x: i16,
     priority_a: u8,
-    y: i8,
+    y: i16,
     tile: u8,
     priority_b: u8,
 }
@@ -30,12 +120,12 @@ impl FrameMap {
 
     #[inline(always)]
     pub fn x(&self) -> i16 {
-        self.x as i16
+        self.x
     }
 
     #[inline(always)]
     pub fn y(&self) -> i16 {
-        self.y as i16
+        self.y
     }
 
     #[inline(always)]
@@ -48,49 +138,119 @@ impl FrameMap {
         self.priority_b & (1 << 7) > 0
     }
 
+    /// Which of the 8 OBJ palettes the part uses
+    #[inline(always)]
+    pub fn palette(&self) -> u8 {
+        (self.priority_b >> 1) & 0b111
+    }
+
+    #[inline(always)]
+    pub fn priority(&self) -> u8 {
+        (self.priority_b >> 4) & 0b11
+    }
+
     #[inline(always)]
     pub fn load_next_page(&self) -> bool {
         self.priority_b & 1 > 0
     }
 
+    /// The full 9 bit OBJ tile number. The name table bit picks the second
+    /// page of 256 tiles, which sits right after the first in `tiles`.
+    #[inline(always)]
+    pub fn tile_number(&self) -> usize {
+        (if self.load_next_page() { 0x100 } else { 0 }) | self.tile as usize
+    }
+
+    /// The four tiles of a 16x16 part. Like the PPU, the column and row
+    /// wrap around inside the 16x16 grid of their own page.
+    pub fn block_tile_numbers(&self) -> [usize; 4] {
+        let n = self.tile_number();
+        let page = n & 0x100;
+        let right = |t: usize| (t & !0x0F) | ((t + 1) & 0x0F);
+        let below = |t: usize| page | ((t + 0x10) & 0xFF);
+        [n, right(n), below(n), below(right(n))]
+    }
+
     pub fn from_rom(rom: &Rom, snes_addr: SnesAddress, offset: usize) -> Vec<Self> {
         // println!("snes addr: {:?}, offset: {:X}", snes_addr, offset);
         let addr = snes_addr.to_pc() + offset;
-        let num_parts = LittleEndian::read_u16(&rom.read(addr, 2)) as usize;
-        rom.read(addr+2, 5*num_parts)
+        rom.read_counted(addr, 5)
             .chunks(5)
             .map(FrameMap::from_slice)
             .collect()
     }
 
     pub fn from_slice(slice: &[u8]) -> Self {
+        // X is 9 bits wide, the low bit of the second byte is its sign
+        let x = (((slice[1] as u16 & 1) << 8) | slice[0] as u16) as i16;
         FrameMap {
-            x: slice[0] as i8,
+            x: if x & 0x100 > 0 { x - 0x200 } else { x },
             priority_a: slice[1],
-            y: slice[2] as i8,
+            y: slice[2] as i8 as i16,
             tile: slice[3],
             priority_b: slice[4],
         }
     }
 
-    pub fn composite(frame_maps: &[FrameMap], tiles: &[Tile], duration: u16, v_offset: u8) -> CompositedFrame {
-        let (zx, zy, width, height) = dimensions(frame_maps);
+    /// A lone 8x8 part, for sprites we put together ourselves
+    pub fn single(x: i16, y: i16, tile: u8, flip_horizontal: bool, palette: u8) -> Self {
+        FrameMap {
+            x,
+            priority_a: 0,
+            y,
+            tile,
+            priority_b: (if flip_horizontal { 1 << 6 } else { 0 }) | (palette & 0b111) << 1,
+        }
+    }
+
+    /// The same part, moved by `(dx, dy)`. Extended spritemaps place whole
+    /// spritemaps at an offset from the enemy's position. Garbage offsets
+    /// wrap around instead of overflowing, the hardware only keeps 9 bits.
+    pub fn translated(&self, dx: i16, dy: i16) -> Self {
+        FrameMap {
+            x: self.x.wrapping_add(dx),
+            y: self.y.wrapping_add(dy),
+            ..self.clone()
+        }
+    }
+
+    pub fn composite(frame_maps: &[FrameMap], tiles: &[Tile], duration: u16, v_offset: i16) -> CompositedFrame {
+        FrameMap::composite_with_hitboxes(frame_maps, tiles, &[], 0, duration, v_offset)
+    }
+
+    /// Same as `composite`, but outlines every hitbox on top of the sprite
+    /// using palette index `color`. The canvas grows to fit the hitboxes.
+    pub fn composite_with_hitboxes(frame_maps: &[FrameMap], tiles: &[Tile], hitboxes: &[Rect], color: u8, duration: u16, v_offset: i16) -> CompositedFrame {
+        FrameMap::composite_with_diagnostics(frame_maps, tiles, hitboxes, color, duration, v_offset).0
+    }
+
+    /// Same as `composite_with_hitboxes`, but also says which parts couldn't
+    /// be drawn. Those get skipped instead of stopping the whole frame.
+    pub fn composite_with_diagnostics(frame_maps: &[FrameMap], tiles: &[Tile], hitboxes: &[Rect], color: u8, duration: u16, v_offset: i16) -> (CompositedFrame, Vec<SkippedPart>) {
+        let (zx, zy, width, height) = dimensions(frame_maps, hitboxes);
+        // Short poses move the origin up, make room if it ends up above the canvas
+        let pad = cmp::max(0, -(zy as i32 + v_offset as i32)) as u16;
+        let (zy, height) = (zy + pad, height + pad);
+        let mut skipped = Vec::new();
 
         let mut buffer = vec![0; width as usize * height as usize];
+        let mut priority = vec![0; width as usize * height as usize];
+        let mut canvas = Canvas { buffer: &mut buffer, priorities: &mut priority, width, zero: (zx, zy) };
 
-        for part in frame_maps.iter().rev() {
+        for (index, part) in frame_maps.iter().enumerate().rev() {
             if part.is_double() {
-                let n = part.tile as usize;
-                for i in [n, n + 1, n + 16, n + 17].iter() {
-                    if *i >= tiles.len() {
-                        panic!("Frame part wants tile {} but we only have {}. Try a lower number of frames.", i, tiles.len());
-                    }
+                let block = part.block_tile_numbers();
+                if let Some(&tile) = block.iter().find(|&&i| i >= tiles.len()) {
+                    skipped.push(SkippedPart {
+                        index,
+                        reason: SkipReason::MissingTile { tile, available: tiles.len() },
+                    });
+                    continue;
                 }
-                // let n = if part.load_next_page() { n - 32 } else { n };
-                let mut tile0 = &tiles[n];
-                let mut tile1 = &tiles[n + 1];
-                let mut tile2 = &tiles[n + 16];
-                let mut tile3 = &tiles[n + 17];
+                let mut tile0 = &tiles[block[0]];
+                let mut tile1 = &tiles[block[1]];
+                let mut tile2 = &tiles[block[2]];
+                let mut tile3 = &tiles[block[3]];
                 if part.flip_horizontal() {
                     mem::swap(&mut tile0, &mut tile1);
                     mem::swap(&mut tile2, &mut tile3);
@@ -99,23 +259,33 @@ impl FrameMap {
                     mem::swap(&mut tile0, &mut tile2);
                     mem::swap(&mut tile1, &mut tile3);
                 }
-                centered_canvas::paint_block(&mut buffer, width, (zx, zy), (tile0, tile1, tile2, tile3), (part.x(), part.y()), part.flip_horizontal(), part.flip_vertical());
+                canvas.paint_block((tile0, tile1, tile2, tile3), (part.x(), part.y()), part.flip_horizontal(), part.flip_vertical(), (part.palette(), part.priority()));
             } else {
-                if part.tile as usize >= tiles.len() {
-                    panic!("Frame part wants tile {} but we only have {}. Try a lower number of frames.", part.tile, tiles.len());
+                if part.tile_number() >= tiles.len() {
+                    skipped.push(SkippedPart {
+                        index,
+                        reason: SkipReason::MissingTile { tile: part.tile_number(), available: tiles.len() },
+                    });
+                    continue;
                 }
-                let tile = &tiles[part.tile as usize];
-                centered_canvas::paint_tile(&mut buffer, width, (zx, zy), tile, (part.x(), part.y()), part.flip_horizontal(), part.flip_vertical());
+                let tile = &tiles[part.tile_number()];
+                canvas.paint_tile(tile, (part.x(), part.y()), part.flip_horizontal(), part.flip_vertical(), (part.palette(), part.priority()));
             }
         }
-        CompositedFrame {
-            buffer: buffer,
-            width: width,
-            height: height,
-            zero_x: zx,
-            zero_y: zy + v_offset as u16,
-            duration: duration,
+        for hitbox in hitboxes {
+            centered_canvas::paint_outline(&mut buffer, width, (zx, zy), *hitbox, color);
         }
+        let frame = CompositedFrame {
+            buffer,
+            priority,
+            width,
+            height,
+            zero_x: zx,
+            zero_y: (zy as i32 + v_offset as i32) as u16,
+            duration,
+        };
+        skipped.reverse();
+        (frame, skipped)
     }
 }
 
@@ -128,17 +298,141 @@ impl fmt::Debug for FrameMap {
     }
 }
 
-fn dimensions(frame_maps: &[FrameMap]) -> (u16, u16, u16, u16) {
-    let mut top = 0i16;
-    let mut bottom = 0i16;
-    let mut left = 0i16;
-    let mut right = 0i16;
+// How far from the origin the canvas can reach. Anything further out, like
+// a hitbox read from garbage, gets cut off instead of growing the canvas.
+const MAX_REACH: i32 = 0x100;
+
+fn dimensions(frame_maps: &[FrameMap], hitboxes: &[Rect]) -> (u16, u16, u16, u16) {
+    // Wide enough that odd coordinates can't overflow while we add them up
+    let mut top = 0i32;
+    let mut bottom = 0i32;
+    let mut left = 0i32;
+    let mut right = 0i32;
     for map in frame_maps.iter() {
         let size = if map.is_double() { 16 } else { 8 };
-        if map.x() < left { left = map.x() };
-        if map.x() + size > right { right = map.x() + size };
-        if map.y() < top { top = map.y() };
-        if map.y() + size > bottom { bottom = map.y() + size }
+        let (x, y) = (map.x() as i32, map.y() as i32);
+        if x < left { left = x };
+        if x + size > right { right = x + size };
+        if y < top { top = y };
+        if y + size > bottom { bottom = y + size }
     }
+    for &(l, t, r, b) in hitboxes.iter() {
+        let (l, t, r, b) = (l as i32, t as i32, r as i32, b as i32);
+        if l < left { left = l };
+        if r + 1 > right { right = r + 1 };
+        if t < top { top = t };
+        if b + 1 > bottom { bottom = b + 1 };
+    }
+    let (left, top) = (cmp::max(left, -MAX_REACH), cmp::max(top, -MAX_REACH));
+    let (right, bottom) = (cmp::min(right, MAX_REACH), cmp::min(bottom, MAX_REACH));
     (-left as u16, -top as u16, (right - left) as u16, (bottom - top) as u16)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameMap, SkippedPart, SkipReason};
+    use snes_bitplanes::Tile;
+
+    // Two pages of blank tiles, with a few painted a solid color
+    fn tiles(painted: &[(usize, u8)]) -> Vec<Tile> {
+        let mut tiles = vec![Tile([0; 64]); 0x200];
+        for &(n, color) in painted {
+            tiles[n] = Tile([color; 64]);
+        }
+        tiles
+    }
+
+    fn pixel(frame: &super::CompositedFrame, x: u16, y: u16) -> u8 {
+        frame.buffer[((frame.zero_y + y) * frame.width + frame.zero_x + x) as usize]
+    }
+
+    #[test]
+    fn first_page() {
+        let tiles = tiles(&[(0x05, 7), (0x105, 3)]);
+        let part = FrameMap::from_slice(&[0x00, 0x00, 0x00, 0x05, 0x00]);
+        assert_eq!(part.tile_number(), 0x05);
+        let frame = FrameMap::composite(&[part], &tiles, 1, 0);
+        assert_eq!(pixel(&frame, 0, 0), 7);
+        assert_eq!(pixel(&frame, 7, 7), 7);
+    }
+
+    #[test]
+    fn second_page() {
+        let tiles = tiles(&[(0x05, 7), (0x105, 3)]);
+        let part = FrameMap::from_slice(&[0x00, 0x00, 0x00, 0x05, 0x01]);
+        assert_eq!(part.tile_number(), 0x105);
+        let frame = FrameMap::composite(&[part], &tiles, 1, 0);
+        assert_eq!(pixel(&frame, 0, 0), 3);
+        assert_eq!(pixel(&frame, 7, 7), 3);
+    }
+
+    #[test]
+    fn double_part_on_second_page() {
+        let tiles = tiles(&[(0x122, 1), (0x123, 2), (0x132, 3), (0x133, 4), (0x022, 9)]);
+        let part = FrameMap::from_slice(&[0x00, 0x80, 0x00, 0x22, 0x01]);
+        assert_eq!(part.block_tile_numbers(), [0x122, 0x123, 0x132, 0x133]);
+        let frame = FrameMap::composite(&[part], &tiles, 1, 0);
+        assert_eq!(pixel(&frame, 0, 0), 1);
+        assert_eq!(pixel(&frame, 8, 0), 2);
+        assert_eq!(pixel(&frame, 0, 8), 3);
+        assert_eq!(pixel(&frame, 8, 8), 4);
+    }
+
+    #[test]
+    fn double_part_wraps_inside_its_page() {
+        // Last column and last row of the second page: the neighbors wrap
+        // back to the start of the same row and column, never into page one
+        let part = FrameMap::from_slice(&[0x00, 0x80, 0x00, 0xFF, 0x01]);
+        assert_eq!(part.block_tile_numbers(), [0x1FF, 0x1F0, 0x10F, 0x100]);
+
+        let part = FrameMap::from_slice(&[0x00, 0x80, 0x00, 0x0F, 0x00]);
+        assert_eq!(part.block_tile_numbers(), [0x00F, 0x000, 0x01F, 0x010]);
+    }
+
+    #[test]
+    fn missing_tiles_are_skipped() {
+        let tiles = tiles(&[(0x05, 7)]);
+        let parts = [
+            FrameMap::from_slice(&[0x00, 0x00, 0x00, 0x05, 0x00]),
+            FrameMap::from_slice(&[0x08, 0x00, 0x00, 0xFF, 0x01]),
+            FrameMap::from_slice(&[0x00, 0x80, 0x08, 0xFF, 0x00]),
+        ];
+        let (frame, skipped) = FrameMap::composite_with_diagnostics(&parts[..1], &tiles[..0x10], &[], 0, 1, 0);
+        assert!(skipped.is_empty());
+        assert_eq!(pixel(&frame, 0, 0), 7);
+
+        let (frame, skipped) = FrameMap::composite_with_diagnostics(&parts, &tiles[..0x10], &[], 0, 1, 0);
+        assert_eq!(skipped, vec![
+            SkippedPart { index: 1, reason: SkipReason::MissingTile { tile: 0x1FF, available: 0x10 } },
+            SkippedPart { index: 2, reason: SkipReason::MissingTile { tile: 0xFF, available: 0x10 } },
+        ]);
+        assert_eq!(pixel(&frame, 0, 0), 7);
+        assert_eq!(pixel(&frame, 8, 0), 0);
+    }
+
+    #[test]
+    fn flipped_double_part() {
+        let tiles = tiles(&[(0x100, 1), (0x101, 2), (0x110, 3), (0x111, 4)]);
+        let part = FrameMap::from_slice(&[0x00, 0x80, 0x00, 0x00, 0x41]);
+        let frame = FrameMap::composite(&[part], &tiles, 1, 0);
+        assert_eq!(pixel(&frame, 0, 0), 2);
+        assert_eq!(pixel(&frame, 8, 0), 1);
+        assert_eq!(pixel(&frame, 0, 8), 4);
+        assert_eq!(pixel(&frame, 8, 8), 3);
+    }
+
+    #[test]
+    fn garbage_offsets_wrap() {
+        let part = FrameMap::single(0x10, -0x10, 0, false, 0).translated(i16::MAX, i16::MIN);
+        assert_eq!((part.x(), part.y()), (i16::MIN + 0x0F, i16::MAX - 0x0F));
+    }
+
+    #[test]
+    fn far_off_hitboxes_are_cut_off() {
+        let part = FrameMap::from_slice(&[0x00, 0x00, 0x00, 0x05, 0x00]);
+        let hitbox = (i16::MIN, i16::MIN, i16::MAX, i16::MAX);
+        let frame = FrameMap::composite_with_hitboxes(&[part], &tiles(&[]), &[hitbox], 1, 1, 0);
+        assert_eq!((frame.width, frame.height), (0x200, 0x200));
+        assert_eq!((frame.zero_x, frame.zero_y), (0x100, 0x100));
+    }
+}
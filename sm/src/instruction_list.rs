@@ -0,0 +1,364 @@
+// Instruction lists. Every enemy bank starts with the same block of common
+// routines, so the enemy instruction pointers below are valid in all of them.
+// Anything else in the >= $8000 range is an enemy-specific routine whose
+// argument length we can't know without disassembling it.
+
+use snes::{Rom, SnesAddress};
+use byteorder::{ByteOrder, LittleEndian};
+use std::fmt;
+
+/// What an instruction pointer does, so the same interpreter can run enemy
+/// lists and the lists of other objects that use a different set of routines
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Opcode {
+    Delete,
+    Call,
+    CallWithA,
+    CallExternal,
+    CallExternalWithA,
+    GoTo,
+    GoToRelative,
+    DecrementTimerGoTo,
+    SetTimer,
+    Sleep,
+    Wait,
+    TransferToVram,
+    EnableOffscreenProcessing,
+    DisableOffscreenProcessing,
+    /// A routine that doesn't affect the animation, with this many bytes of arguments
+    Skip(u16),
+}
+
+pub type InstructionSet = [(u16, Opcode)];
+
+pub static ENEMY_INSTRUCTIONS: &InstructionSet = &[
+    (0x807C, Opcode::Delete),
+    (0x8093, Opcode::Call),
+    (0x80A1, Opcode::CallWithA),
+    (0x80B5, Opcode::CallExternal),
+    (0x80CE, Opcode::CallExternalWithA),
+    (0x80ED, Opcode::GoTo),
+    (0x80F2, Opcode::GoToRelative),
+    (0x8110, Opcode::DecrementTimerGoTo),
+    (0x8118, Opcode::DecrementTimerGoTo),
+    (0x8123, Opcode::SetTimer),
+    (0x812F, Opcode::Sleep),
+    (0x813A, Opcode::Wait),
+    (0x8146, Opcode::TransferToVram),
+    (0x8159, Opcode::EnableOffscreenProcessing),
+    (0x8167, Opcode::DisableOffscreenProcessing),
+];
+
+// Bail out of lists that never reach a terminator (or a loop we recognize),
+// counting every instruction run, timer loops included
+const MAX_STEPS: usize = 0x400;
+// No enemy has anywhere near this many, so anything more is a misread
+const MAX_LISTS: usize = 0x100;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Frame { duration: u16, spritemap: u16 },
+    Delete,
+    Call(u16),
+    CallWithA(u16, u16),
+    CallExternal(u32),
+    CallExternalWithA(u32, u16),
+    GoTo(u16),
+    GoToRelative(i8),
+    DecrementTimerGoTo(u16),
+    SetTimer(u16),
+    Sleep,
+    Wait(u16),
+    TransferToVram { size: u16, source: u32, destination: u16 },
+    EnableOffscreenProcessing,
+    DisableOffscreenProcessing,
+    Other { op: u16, size: u16 },
+    Unknown(u16),
+}
+
+impl Instruction {
+    pub fn read(rom: &Rom, addr: SnesAddress, set: &InstructionSet) -> Self {
+        let addr = addr.to_pc();
+        let word = |n: usize| LittleEndian::read_u16(rom.read(addr + n, 2));
+        let long = |n: usize| LittleEndian::read_u24(rom.read(addr + n, 3));
+        let op = word(0);
+        if op < 0x8000 {
+            return Instruction::Frame { duration: op, spritemap: word(2) };
+        }
+        let opcode = match set.iter().find(|&&(pointer, _)| pointer == op) {
+            Some(&(_, opcode)) => opcode,
+            None => return Instruction::Unknown(op),
+        };
+        match opcode {
+            Opcode::Delete => Instruction::Delete,
+            Opcode::Call => Instruction::Call(word(2)),
+            Opcode::CallWithA => Instruction::CallWithA(word(2), word(4)),
+            Opcode::CallExternal => Instruction::CallExternal(long(2)),
+            Opcode::CallExternalWithA => Instruction::CallExternalWithA(long(2), word(5)),
+            Opcode::GoTo => Instruction::GoTo(word(2)),
+            Opcode::GoToRelative => Instruction::GoToRelative(rom[addr + 2] as i8),
+            Opcode::DecrementTimerGoTo => Instruction::DecrementTimerGoTo(word(2)),
+            Opcode::SetTimer => Instruction::SetTimer(word(2)),
+            Opcode::Sleep => Instruction::Sleep,
+            Opcode::Wait => Instruction::Wait(word(2)),
+            Opcode::TransferToVram => Instruction::TransferToVram {
+                size: word(2),
+                source: long(4),
+                destination: word(7),
+            },
+            Opcode::EnableOffscreenProcessing => Instruction::EnableOffscreenProcessing,
+            Opcode::DisableOffscreenProcessing => Instruction::DisableOffscreenProcessing,
+            Opcode::Skip(size) => Instruction::Other { op, size: 2 + size },
+        }
+    }
+
+    /// Number of bytes the instruction and its arguments take up
+    pub fn size(&self) -> u16 {
+        match *self {
+            Instruction::Frame { .. } => 4,
+            Instruction::Delete |
+            Instruction::Sleep |
+            Instruction::EnableOffscreenProcessing |
+            Instruction::DisableOffscreenProcessing |
+            Instruction::Unknown(_) => 2,
+            Instruction::GoToRelative(_) => 3,
+            Instruction::Call(_) |
+            Instruction::GoTo(_) |
+            Instruction::DecrementTimerGoTo(_) |
+            Instruction::SetTimer(_) |
+            Instruction::Wait(_) => 4,
+            Instruction::CallExternal(_) => 5,
+            Instruction::CallWithA(_, _) => 6,
+            Instruction::CallExternalWithA(_, _) => 7,
+            Instruction::TransferToVram { .. } => 9,
+            Instruction::Other { size, .. } => size,
+        }
+    }
+
+    fn ends_list(&self) -> bool {
+        matches!(*self, Instruction::Delete | Instruction::GoTo(_) | Instruction::GoToRelative(_) | Instruction::Sleep)
+    }
+}
+
+impl fmt::Debug for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Frame { duration, spritemap } => write!(f, "Frame({:04X}, ${:04X})", duration, spritemap),
+            Instruction::Delete => write!(f, "Delete"),
+            Instruction::Call(r) => write!(f, "Call(${:04X})", r),
+            Instruction::CallWithA(r, a) => write!(f, "CallWithA(${:04X}, {:04X})", r, a),
+            Instruction::CallExternal(r) => write!(f, "CallExternal(${:06X})", r),
+            Instruction::CallExternalWithA(r, a) => write!(f, "CallExternalWithA(${:06X}, {:04X})", r, a),
+            Instruction::GoTo(a) => write!(f, "GoTo(${:04X})", a),
+            Instruction::GoToRelative(o) => write!(f, "GoToRelative({})", o),
+            Instruction::DecrementTimerGoTo(a) => write!(f, "DecrementTimerGoTo(${:04X})", a),
+            Instruction::SetTimer(t) => write!(f, "SetTimer({:04X})", t),
+            Instruction::Sleep => write!(f, "Sleep"),
+            Instruction::Wait(t) => write!(f, "Wait({:04X})", t),
+            Instruction::TransferToVram { size, source, destination } =>
+                write!(f, "TransferToVram({:04X}, ${:06X}, {:04X})", size, source, destination),
+            Instruction::EnableOffscreenProcessing => write!(f, "EnableOffscreenProcessing"),
+            Instruction::DisableOffscreenProcessing => write!(f, "DisableOffscreenProcessing"),
+            Instruction::Other { op, size } => write!(f, "Other(${:04X}, {})", op, size),
+            Instruction::Unknown(op) => write!(f, "Unknown(${:04X})", op),
+        }
+    }
+}
+
+/// What happens once the last frame of a sequence has played
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum End {
+    /// Resume playing from this frame index of the same sequence
+    Loop(usize),
+    /// Continue with the instruction list at this address (same bank)
+    GoTo(u16),
+    /// Hold the last frame forever
+    Sleep,
+    /// The enemy deletes itself
+    Delete,
+    /// Hit an enemy-specific instruction we can't step over
+    Unknown(u16),
+    /// Gave up at this address after running too many instructions without
+    /// getting anywhere
+    StepLimit(u16),
+}
+
+pub struct Sequence {
+    /// Pairs of (duration, spritemap pointer), in play order
+    pub frames: Vec<(u16, u16)>,
+    pub end: End,
+}
+
+/// Runs the instruction list at `addr` the way the game would, unrolling
+/// timer loops and following jumps until it either loops back on itself,
+/// jumps somewhere new, or stops.
+pub fn sequence(rom: &Rom, addr: SnesAddress, set: &InstructionSet) -> Sequence {
+    let bank = addr.0 & 0xFF0000;
+    let mut pc = addr.0 as u16;
+    let mut frames: Vec<(u16, u16)> = Vec::new();
+    let mut visited: Vec<(u16, usize)> = Vec::new();
+    let mut timer = 0u16;
+    let mut steps = 0;
+
+    let end = 'run: loop {
+        if steps >= MAX_STEPS {
+            break 'run End::StepLimit(pc);
+        }
+        steps += 1;
+        if !visited.iter().any(|&(a, _)| a == pc) {
+            visited.push((pc, frames.len()));
+        }
+        let instruction = Instruction::read(rom, SnesAddress(bank | pc as u32), set);
+        let next = pc.wrapping_add(instruction.size());
+        let target = match instruction {
+            Instruction::Frame { duration, spritemap } => {
+                frames.push((duration, spritemap));
+                next
+            },
+            Instruction::Wait(duration) => {
+                if let Some(last) = frames.last_mut() {
+                    last.0 = last.0.saturating_add(duration);
+                }
+                next
+            },
+            Instruction::SetTimer(t) => {
+                timer = t;
+                next
+            },
+            Instruction::DecrementTimerGoTo(addr) => {
+                timer = timer.saturating_sub(1);
+                if timer != 0 { addr } else { next }
+            },
+            Instruction::GoTo(addr) => break 'run jump(&visited, addr),
+            Instruction::GoToRelative(offset) => {
+                let addr = pc.wrapping_add(2).wrapping_add(offset as i16 as u16);
+                break 'run jump(&visited, addr);
+            },
+            Instruction::Sleep => break 'run End::Sleep,
+            Instruction::Delete => break 'run End::Delete,
+            Instruction::Unknown(op) => break 'run End::Unknown(op),
+            _ => next,
+        };
+        pc = target;
+    };
+
+    Sequence { frames, end }
+}
+
+fn jump(visited: &[(u16, usize)], addr: u16) -> End {
+    match visited.iter().find(|&&(a, _)| a == addr) {
+        Some(&(_, index)) => End::Loop(index),
+        None => End::GoTo(addr),
+    }
+}
+
+/// Finds the instruction lists laid out back to back starting at `addr`,
+/// stopping at the first thing that doesn't look like one, or after
+/// `MAX_LISTS` of them.
+pub fn scan(rom: &Rom, addr: SnesAddress, set: &InstructionSet) -> Vec<SnesAddress> {
+    let bank = addr.0 & 0xFF0000;
+    let mut pc = addr.0 as u16;
+    let mut lists = Vec::new();
+
+    'lists: while lists.len() < MAX_LISTS {
+        let start = pc;
+        let mut num_frames = 0;
+        for _ in 0..MAX_STEPS {
+            let instruction = Instruction::read(rom, SnesAddress(bank | pc as u32), set);
+            match instruction {
+                Instruction::Frame { duration, spritemap } => {
+                    if duration == 0 || spritemap < 0x8000 {
+                        break 'lists;
+                    }
+                    num_frames += 1;
+                },
+                Instruction::Unknown(_) => break 'lists,
+                _ => (),
+            }
+            pc = pc.wrapping_add(instruction.size());
+            if instruction.ends_list() {
+                if num_frames == 0 {
+                    break 'lists;
+                }
+                lists.push(SnesAddress(bank | start as u32));
+                continue 'lists;
+            }
+        }
+        break;
+    }
+    lists
+}
+
+#[cfg(test)]
+mod tests {
+    use snes::{Rom, SnesAddress};
+    use std::cmp;
+    use super::{scan, sequence, End, ENEMY_INSTRUCTIONS, MAX_LISTS};
+
+    const START: SnesAddress = SnesAddress(0x808000);
+    const GOTO: u16 = 0x80ED;
+    const DECREMENT_TIMER_GOTO: u16 = 0x8110;
+    const SET_TIMER: u16 = 0x8123;
+    const SLEEP: u16 = 0x812F;
+    const WAIT: u16 = 0x813A;
+
+    // A list of words, assembled at $80:8000
+    fn assemble(words: &[u16]) -> Vec<u8> {
+        let mut data = vec![0; cmp::max(0x100, words.len() * 2)];
+        for (n, &word) in words.iter().enumerate() {
+            data[n * 2] = word as u8;
+            data[n * 2 + 1] = (word >> 8) as u8;
+        }
+        data
+    }
+
+    #[test]
+    fn loops_back_to_the_start() {
+        let data = assemble(&[2, 0x9000, 3, 0x9004, GOTO, 0x8000]);
+        let s = sequence(&Rom(&data), START, ENEMY_INSTRUCTIONS);
+        assert_eq!(s.frames, vec![(2, 0x9000), (3, 0x9004)]);
+        assert_eq!(s.end, End::Loop(0));
+    }
+
+    #[test]
+    fn jumps_somewhere_new() {
+        let data = assemble(&[2, 0x9000, GOTO, 0x8040]);
+        let s = sequence(&Rom(&data), START, ENEMY_INSTRUCTIONS);
+        assert_eq!(s.end, End::GoTo(0x8040));
+    }
+
+    #[test]
+    fn timers_unroll() {
+        let data = assemble(&[SET_TIMER, 3, 1, 0x9000, DECREMENT_TIMER_GOTO, 0x8004, SLEEP]);
+        let s = sequence(&Rom(&data), START, ENEMY_INSTRUCTIONS);
+        assert_eq!(s.frames, vec![(1, 0x9000); 3]);
+        assert_eq!(s.end, End::Sleep);
+    }
+
+    #[test]
+    fn long_timers_give_up() {
+        let data = assemble(&[SET_TIMER, 0xFFFF, 1, 0x9000, DECREMENT_TIMER_GOTO, 0x8004, SLEEP]);
+        let s = sequence(&Rom(&data), START, ENEMY_INSTRUCTIONS);
+        assert!(s.frames.len() < 0x400);
+        // Given up partway through the timer loop, not at an instruction
+        match s.end {
+            End::StepLimit(pc) => assert!((0x8004..0x800C).contains(&pc)),
+            end => panic!("ran to {:?}", end),
+        }
+    }
+
+    #[test]
+    fn scans_stop_eventually() {
+        let words: Vec<_> = (0..0x200).flat_map(|_| vec![1, 0x9000, SLEEP]).collect();
+        let lists = scan(&Rom(&assemble(&words)), START, ENEMY_INSTRUCTIONS);
+        assert_eq!(lists.len(), MAX_LISTS);
+        assert_eq!(lists[1], SnesAddress(0x808006));
+    }
+
+    #[test]
+    fn waits_dont_overflow() {
+        let data = assemble(&[0x7FF0, 0x9000, WAIT, 0xF000, SLEEP]);
+        let s = sequence(&Rom(&data), START, ENEMY_INSTRUCTIONS);
+        assert_eq!(s.frames, vec![(0xFFFF, 0x9000)]);
+    }
+}
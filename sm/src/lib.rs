@@ -1,9 +1,20 @@
 extern crate byteorder;
 extern crate snes_bitplanes;
 extern crate lib_samus;
+extern crate png;
+#[cfg(feature = "webp")]
+extern crate image_webp;
 
 pub mod enemy;
+pub mod instruction_list;
+pub mod projectile;
+pub mod weapons;
+pub mod aseprite;
+pub mod atlas;
 pub mod centered_canvas;
+pub mod echo;
+pub mod palette;
+pub mod export;
 pub mod sprite;
 pub mod util;
 
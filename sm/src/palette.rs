@@ -0,0 +1,247 @@
+// Palette files, for getting colors in and out of paint programs. Everything
+// but CGRAM dumps stores 8 bit RGB, so colors go through a `ColorConversion`
+// both ways, and lose their low bits for good on the way in.
+//
+// JASC .pal (Paint Shop Pro, Aseprite, ...)  text, "r g b" per line
+// GIMP .gpl                                  text, "r g b name" per line
+// Adobe .act                                 256 RGB triples, and optionally a
+//                                            count and transparent index
+// YY-CHR .pal                                256 RGB triples
+// CGRAM                                      little endian BGR555 words, the
+//                                            way the SNES keeps them
+
+use std::fmt;
+use std::fmt::Write;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use util::{ColorConversion, RGBu8};
+
+const JASC_HEADER: &str = "JASC-PAL";
+const JASC_VERSION: &str = "0100";
+const GIMP_HEADER: &str = "GIMP Palette";
+const RGB_PALETTE_SIZE: usize = 256 * 3;
+const ACT_FOOTER_SIZE: usize = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    Jasc,
+    Gimp,
+    Act,
+    YyChr,
+    Cgram,
+}
+
+impl Format {
+    /// Picks a format from the file's extension, looking inside .pal files
+    /// to tell JASC's text apart from YY-CHR's bytes
+    pub fn guess(filename: &str, data: &[u8]) -> Option<Format> {
+        let extension = match filename.rfind('.') {
+            Some(dot) => filename[dot + 1..].to_lowercase(),
+            None => return None,
+        };
+        match extension.as_str() {
+            "pal" if data.starts_with(JASC_HEADER.as_bytes()) => Some(Format::Jasc),
+            "pal" => Some(Format::YyChr),
+            "gpl" => Some(Format::Gimp),
+            "act" => Some(Format::Act),
+            "cgram" | "bin" | "cgr" => Some(Format::Cgram),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The file doesn't start the way this format has to
+    BadHeader,
+    /// Couldn't make out a color on this line (counting from 1)
+    BadColor(usize),
+    /// The file's the wrong length for this format
+    BadSize(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::BadHeader => write!(f, "not a palette file of that kind"),
+            Error::BadColor(line) => write!(f, "couldn't read a color on line {}", line),
+            Error::BadSize(size) => write!(f, "{} bytes is the wrong size for that kind of palette", size),
+        }
+    }
+}
+
+pub fn read(data: &[u8], format: Format, conversion: ColorConversion) -> Result<Vec<u16>, Error> {
+    let colors = match format {
+        Format::Jasc => read_jasc(data)?,
+        Format::Gimp => read_gimp(data)?,
+        Format::Act => read_act(data)?,
+        Format::YyChr => read_rgb(data)?,
+        Format::Cgram => return read_cgram(data),
+    };
+    Ok(colors.iter().map(|c| conversion.bgr555(c)).collect())
+}
+
+pub fn write(palette: &[u16], format: Format, conversion: ColorConversion) -> Vec<u8> {
+    let colors: Vec<RGBu8> = palette.iter().map(|c| conversion.rgb888(c)).collect();
+    match format {
+        Format::Jasc => write_jasc(&colors),
+        Format::Gimp => write_gimp(&colors, "Super Metroid"),
+        Format::Act => write_act(&colors),
+        Format::YyChr => write_rgb(&colors),
+        Format::Cgram => write_cgram(palette),
+    }
+}
+
+fn parse_rgb(line: &str) -> Option<RGBu8> {
+    let mut channels = line.split_whitespace().map(|c| c.parse::<u8>());
+    match (channels.next(), channels.next(), channels.next()) {
+        (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => Some((r, g, b)),
+        _ => None,
+    }
+}
+
+fn read_jasc(data: &[u8]) -> Result<Vec<RGBu8>, Error> {
+    let text = String::from_utf8_lossy(data);
+    let mut lines = text.lines().map(str::trim);
+    if lines.next() != Some(JASC_HEADER) || lines.next() != Some(JASC_VERSION) {
+        return Err(Error::BadHeader);
+    }
+    let count: usize = lines.next().and_then(|n| n.parse().ok()).ok_or(Error::BadColor(3))?;
+    let mut colors = Vec::with_capacity(count);
+    for (n, line) in lines.take(count).enumerate() {
+        colors.push(parse_rgb(line).ok_or(Error::BadColor(n + 4))?);
+    }
+    if colors.len() < count {
+        return Err(Error::BadColor(colors.len() + 4));
+    }
+    Ok(colors)
+}
+
+fn read_gimp(data: &[u8]) -> Result<Vec<RGBu8>, Error> {
+    let text = String::from_utf8_lossy(data);
+    let mut lines = text.lines().map(str::trim).enumerate();
+    match lines.next() {
+        Some((_, GIMP_HEADER)) => {},
+        _ => return Err(Error::BadHeader),
+    }
+    let mut colors = Vec::new();
+    for (n, line) in lines {
+        // Name: and Columns: are optional, and comments can go anywhere
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Name:") || line.starts_with("Columns:") {
+            continue;
+        }
+        colors.push(parse_rgb(line).ok_or(Error::BadColor(n + 1))?);
+    }
+    Ok(colors)
+}
+
+fn read_rgb(data: &[u8]) -> Result<Vec<RGBu8>, Error> {
+    if data.len() != RGB_PALETTE_SIZE {
+        return Err(Error::BadSize(data.len()));
+    }
+    Ok(data.chunks(3).map(|c| (c[0], c[1], c[2])).collect())
+}
+
+fn read_act(data: &[u8]) -> Result<Vec<RGBu8>, Error> {
+    match data.len() {
+        RGB_PALETTE_SIZE => read_rgb(data),
+        n if n == RGB_PALETTE_SIZE + ACT_FOOTER_SIZE => {
+            let count = BigEndian::read_u16(&data[RGB_PALETTE_SIZE..]) as usize;
+            let mut colors = read_rgb(&data[..RGB_PALETTE_SIZE])?;
+            if count > 0 && count < colors.len() {
+                colors.truncate(count);
+            }
+            Ok(colors)
+        },
+        n => Err(Error::BadSize(n)),
+    }
+}
+
+fn read_cgram(data: &[u8]) -> Result<Vec<u16>, Error> {
+    if !data.len().is_multiple_of(2) {
+        return Err(Error::BadSize(data.len()));
+    }
+    // The top bit isn't a color
+    Ok(data.chunks(2).map(|c| LittleEndian::read_u16(c) & 0x7FFF).collect())
+}
+
+fn write_jasc(colors: &[RGBu8]) -> Vec<u8> {
+    let mut text = format!("{}\r\n{}\r\n{}\r\n", JASC_HEADER, JASC_VERSION, colors.len());
+    for &(r, g, b) in colors {
+        write!(text, "{} {} {}\r\n", r, g, b).unwrap();
+    }
+    text.into_bytes()
+}
+
+fn write_gimp(colors: &[RGBu8], name: &str) -> Vec<u8> {
+    let mut text = format!("{}\nName: {}\nColumns: 16\n#\n", GIMP_HEADER, name);
+    for (i, &(r, g, b)) in colors.iter().enumerate() {
+        writeln!(text, "{:3} {:3} {:3}\t{:02X}", r, g, b, i).unwrap();
+    }
+    text.into_bytes()
+}
+
+// Always a full 256 colors, black past the end of the palette
+fn write_rgb(colors: &[RGBu8]) -> Vec<u8> {
+    let mut data: Vec<u8> = colors.iter().take(256)
+        .fold(Vec::with_capacity(RGB_PALETTE_SIZE), |mut v, &(r, g, b)| {
+            v.push(r); v.push(g); v.push(b);
+            v
+        });
+    data.resize(RGB_PALETTE_SIZE, 0);
+    data
+}
+
+fn write_act(colors: &[RGBu8]) -> Vec<u8> {
+    let mut data = write_rgb(colors);
+    let mut footer = [0; ACT_FOOTER_SIZE];
+    BigEndian::write_u16(&mut footer[0..2], colors.len().min(256) as u16);
+    BigEndian::write_u16(&mut footer[2..4], 0); // index 0 is transparent
+    data.extend_from_slice(&footer);
+    data
+}
+
+fn write_cgram(palette: &[u16]) -> Vec<u8> {
+    let mut data = vec![0; palette.len() * 2];
+    for (chunk, &color) in data.chunks_mut(2).zip(palette.iter()) {
+        LittleEndian::write_u16(chunk, color);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use util::ColorConversion;
+    use super::{read, write, Error, Format};
+
+    // Every channel uses all 5 bits somewhere
+    static PALETTE: [u16; 4] = [0x0000, 0x7FFF, 0x001F, 0x5294];
+
+    #[test]
+    fn round_trips() {
+        let conversions = [ColorConversion::Shift, ColorConversion::Replicate, ColorConversion::Gamma];
+        for &format in [Format::Jasc, Format::Gimp, Format::Act, Format::Cgram].iter() {
+            for &conversion in conversions.iter() {
+                let file = write(&PALETTE, format, conversion);
+                assert_eq!(read(&file, format, conversion), Ok(PALETTE.to_vec()), "{:?} {:?}", format, conversion);
+            }
+        }
+        // No count to say where the palette ended
+        let yychr = read(&write(&PALETTE, Format::YyChr, ColorConversion::Replicate), Format::YyChr, ColorConversion::Replicate).unwrap();
+        assert_eq!((&yychr[..4], yychr.len()), (&PALETTE[..], 256));
+    }
+
+    #[test]
+    fn reads_other_programs_files() {
+        let jasc = b"JASC-PAL\n0100\n2\n255 0 0\n0 0 248\n";
+        assert_eq!(read(jasc, Format::Jasc, ColorConversion::Shift), Ok(vec![0x001F, 0x7C00]));
+
+        let gpl = b"GIMP Palette\nName: suit\n#\n  0 255   0\tgreen\n\n# and\n8 8 8\n";
+        assert_eq!(read(gpl, Format::Gimp, ColorConversion::Shift), Ok(vec![0x03E0, 0x0421]));
+
+        assert_eq!(read(b"GIMP Palette\n1 2\n", Format::Gimp, ColorConversion::Shift), Err(Error::BadColor(2)));
+        assert_eq!(read(b"0100\n", Format::Jasc, ColorConversion::Shift), Err(Error::BadHeader));
+        assert_eq!(Format::guess("suit.pal", jasc), Some(Format::Jasc));
+        assert_eq!(Format::guess("suit.PAL", &[0; 768]), Some(Format::YyChr));
+        assert_eq!(Format::guess("act", &[]), None);
+    }
+}
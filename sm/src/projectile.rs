@@ -0,0 +1,187 @@
+// Enemy projectiles: Kihunter acid, Space Pirate lasers, Ridley's fireballs...
+// Their headers and instruction lists live in bank $86, and the spritemaps
+// they point to live in bank $8D. Spritemaps use tile numbers as they are in
+// VRAM, so compositing takes the common sprite tiles plus the graphics of
+// whichever enemy fired them (see `enemy::DNA::vram_graphics`).
+
+use snes::{Rom, SnesAddress};
+use std::fmt;
+use byteorder::{ByteOrder, LittleEndian};
+use frame_map::FrameMap;
+use enemy::{Animation, Frame, Hitbox};
+use instruction_list::{self, InstructionSet, Opcode};
+
+const PROJECTILE_BANK: u32 = 0x860000;
+const SPRITEMAP_BANK: u32 = 0x8D0000;
+const HEADER_SIZE: usize = 14;
+
+// Enemy code spawns a projectile with `LDY #header : JSL routine`, using one
+// of these two routines
+const LDY_IMMEDIATE: u8 = 0xA0;
+const JSL: u8 = 0x22;
+const SPAWN_ROUTINES: [u32; 2] = [0x868027, 0x868097];
+// Enemy AI banks, and $86 itself for projectiles that spawn projectiles
+const SPAWNING_BANKS: [(u32, u32); 2] = [(0x86, 0x86), (0xA0, 0xB3)];
+
+pub static PROJECTILE_INSTRUCTIONS: &InstructionSet = &[
+    (0x8154, Opcode::Delete),
+    (0x8159, Opcode::Sleep),
+    (0x8161, Opcode::Skip(2)), // pre-instruction = [[Y]]
+    (0x816A, Opcode::Skip(0)), // clear pre-instruction
+    (0x8171, Opcode::Call),
+    (0x81AB, Opcode::GoTo),
+    (0x81C6, Opcode::DecrementTimerGoTo),
+    (0x81D5, Opcode::SetTimer),
+];
+
+/// Every projectile header the game's code spawns, in address order. Which
+/// header to spawn is always an immediate operand right before the call to
+/// one of the spawn routines, so this follows those calls.
+pub fn catalog<'a>(rom: &'a Rom) -> Vec<Projectile<'a>> {
+    let mut headers = Vec::new();
+    for &(first, last) in SPAWNING_BANKS.iter() {
+        for bank in first..last + 1 {
+            headers.extend(spawned_headers(rom.read(SnesAddress(bank << 16 | 0x8000).to_pc(), 0x8000)));
+        }
+    }
+    headers.sort();
+    headers.dedup();
+    headers.into_iter()
+        .map(|pointer| Projectile::read_from_rom(rom, SnesAddress(PROJECTILE_BANK | pointer as u32)))
+        .collect()
+}
+
+// Header pointers from every `LDY #header : JSL spawn` in `code`
+fn spawned_headers(code: &[u8]) -> Vec<u16> {
+    let calls: Vec<[u8; 4]> = SPAWN_ROUTINES.iter()
+        .map(|&r| [JSL, r as u8, (r >> 8) as u8, (r >> 16) as u8])
+        .collect();
+    code.windows(7)
+        .filter(|window| window[0] == LDY_IMMEDIATE && calls.iter().any(|call| &window[3..] == call))
+        .map(|window| LittleEndian::read_u16(&window[1..3]))
+        .filter(|&pointer| pointer >= 0x8000)
+        .collect()
+}
+
+pub struct Projectile<'a> {
+    address: SnesAddress,
+    init: u16,
+    pre_instruction: u16,
+    instruction_list: u16,
+    x_radius: u8,
+    y_radius: u8,
+    properties: u16,
+    hit_instruction_list: u16,
+    shot_instruction_list: u16,
+    rom: &'a Rom<'a>,
+}
+
+impl<'a> Projectile<'a> {
+    pub fn read_from_rom(rom: &'a Rom, snes_addr: SnesAddress) -> Self {
+        let header = rom.read(snes_addr.to_pc(), HEADER_SIZE);
+        Projectile {
+            address: snes_addr,
+            init: LittleEndian::read_u16(&header[0..2]),
+            pre_instruction: LittleEndian::read_u16(&header[2..4]),
+            instruction_list: LittleEndian::read_u16(&header[4..6]),
+            x_radius: header[6],
+            y_radius: header[7],
+            properties: LittleEndian::read_u16(&header[8..10]),
+            hit_instruction_list: LittleEndian::read_u16(&header[10..12]),
+            shot_instruction_list: LittleEndian::read_u16(&header[12..14]),
+            rom,
+        }
+    }
+
+    pub fn address(&self) -> SnesAddress {
+        self.address
+    }
+
+    /// Damage dealt to Samus on contact
+    pub fn damage(&self) -> u16 {
+        self.properties & 0x0FFF
+    }
+
+    pub fn animation(&self) -> Animation {
+        self.animation_at(self.instruction_list)
+    }
+
+    /// Played when the projectile hits Samus
+    pub fn hit_animation(&self) -> Option<Animation> {
+        if self.hit_instruction_list == 0 {
+            None
+        } else {
+            Some(self.animation_at(self.hit_instruction_list))
+        }
+    }
+
+    /// Played when Samus shoots the projectile
+    pub fn shot_animation(&self) -> Option<Animation> {
+        if self.shot_instruction_list == 0 {
+            None
+        } else {
+            Some(self.animation_at(self.shot_instruction_list))
+        }
+    }
+
+    pub fn animations(&self) -> Vec<Animation> {
+        let mut animations = vec![self.animation()];
+        animations.extend(self.hit_animation());
+        animations.extend(self.shot_animation());
+        animations
+    }
+
+    fn animation_at(&self, pointer: u16) -> Animation {
+        let addr = SnesAddress(PROJECTILE_BANK | pointer as u32);
+        let sequence = instruction_list::sequence(self.rom, addr, PROJECTILE_INSTRUCTIONS);
+        let hitbox = Hitbox {
+            left: -(self.x_radius as i16),
+            top: -(self.y_radius as i16),
+            right: self.x_radius as i16,
+            bottom: self.y_radius as i16,
+            touch: 0,
+            shot: 0,
+        };
+        let frames = sequence.frames.into_iter()
+            .map(|(duration, spritemap)| {
+                let parts = FrameMap::from_rom(self.rom, SnesAddress(SPRITEMAP_BANK | spritemap as u32), 0);
+                Frame::new(parts, vec![hitbox], duration)
+            })
+            .collect();
+        Animation {
+            name: format!("{:02X}:{:04X}", addr.0 >> 16, addr.0 & 0xFFFF),
+            address: addr,
+            frames,
+            end: sequence.end,
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Projectile<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+            "Projectile {{ address: {:06X}, init: {:04X}, pre_instruction: {:04X}, instructions: {:04X}, radius: ({}, {}), damage: {}, hit: {:04X}, shot: {:04X} }}",
+            self.address.0, self.init, self.pre_instruction, self.instruction_list, self.x_radius, self.y_radius, self.damage(),
+            self.hit_instruction_list, self.shot_instruction_list
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::spawned_headers;
+
+    #[test]
+    fn spawn_calls_are_found() {
+        let code = [
+            0xA0, 0x34, 0x12, 0x22, 0x27, 0x80, 0x86, // LDY #$1234 : JSL $868027, not a header
+            0xA0, 0x00, 0x9C, 0x22, 0x97, 0x80, 0x86, // LDY #$9C00 : JSL $868097
+            0xA0, 0x00, 0xA0, 0x22, 0x27, 0x80, 0x86, // LDY #$A000 : JSL $868027
+            0xA0, 0x00, 0xB0, 0x22, 0x27, 0x80, 0x87, // JSL to somewhere else
+            0xA2, 0x00, 0xC0, 0x22, 0x27, 0x80, 0x86, // LDX, not LDY
+            0xA0, 0x00, 0xD0, 0xEA, 0x22, 0x27, 0x80, // something in between
+            0xA0, 0x00, 0xE0, 0x22, 0x27, 0x80, // cut off
+        ];
+        assert_eq!(spawned_headers(&code), vec![0x9C00, 0xA000]);
+    }
+}
@@ -5,6 +5,7 @@ use byteorder::{ByteOrder, LittleEndian};
 use snes_bitplanes::{Bitplanes, Tile};
 use frame_map::FrameMap;
 use lib_samus::pose::{ControllerInput, Terminator, Transition};
+pub use lib_samus::pose::Direction;
 
 const BASE_TABLES_POINTER: SnesAddress = SnesAddress(0x92808D);
 const BOTTOM_HALF_POINTERS: SnesAddress = SnesAddress(0x92945D);
@@ -20,9 +21,52 @@ const FRAME_DURATION_START: SnesAddress = SnesAddress(0x910000);
 
 const POSE_TRANSITION_TABLE: SnesAddress = SnesAddress(0x919EE2);
 
+const POSE_DEFINITIONS: SnesAddress = SnesAddress(0x91B629);
+const POSE_DEFINITION_SIZE: usize = 8;
+/// How many poses the game has, where the definition table at $91:B629 ends
+pub const NUM_POSES: usize = 0xFD;
+
+// Samus is always this wide, only her height changes between poses
+const X_RADIUS: i16 = 5;
+// Standing's feet are this far below her center, every other pose gets
+// lined up with them
+const STANDING_Y_RADIUS: i16 = 0x15;
+
 const TOP_DMA_LOOKUP: SnesAddress = SnesAddress(0x92D91E);
 const BOTTOM_DMA_LOOKUP: SnesAddress = SnesAddress(0x92D938);
 
+// The arm cannon is its own sprite. Which way it points and where it sits
+// relative to Samus (one spot per animation frame) come from a table indexed
+// by pose; its graphics are one tile per direction.
+const CANNON_POSITION_POINTERS: SnesAddress = SnesAddress(0x90C7DF);
+const CANNON_POSITION_BANK: u32 = 0x900000;
+const CANNON_TILE_POINTERS: SnesAddress = SnesAddress(0x90C791);
+const CANNON_TILE_BANK: u32 = 0x9A0000;
+const NUM_CANNON_DIRECTIONS: u8 = 10;
+
+// Palette pointer tables live in bank $91, the palettes themselves in $9B.
+// The power suit palette is the one at $9B:9400 we used to hardcode.
+const PALETTE_BANK: u32 = 0x9B0000;
+
+struct PaletteTable {
+    name: &'static str,
+    pointers: SnesAddress,
+    per_suit: bool,
+    frames: usize,
+}
+
+static SUITS: [&str; 3] = ["power", "varia", "gravity"];
+
+static PALETTE_TABLES: [PaletteTable; 7] = [
+    PaletteTable { name: "", pointers: SnesAddress(0x91D7D5), per_suit: true, frames: 1 },
+    PaletteTable { name: "charge", pointers: SnesAddress(0x91D7DB), per_suit: true, frames: 8 },
+    PaletteTable { name: "speed_booster", pointers: SnesAddress(0x91D80B), per_suit: true, frames: 4 },
+    PaletteTable { name: "screw_attack", pointers: SnesAddress(0x91D823), per_suit: true, frames: 3 },
+    PaletteTable { name: "shinespark", pointers: SnesAddress(0x91D835), per_suit: true, frames: 4 },
+    PaletteTable { name: "hurt_flash", pointers: SnesAddress(0x91D84D), per_suit: false, frames: 1 },
+    PaletteTable { name: "visor", pointers: SnesAddress(0x91D84F), per_suit: false, frames: 3 },
+];
+
 pub fn tilemaps(rom: &Rom, state: usize, num_frames: usize) -> Vec<Vec<FrameMap>> {
     let (bottom_pointers, top_pointers) = lookup_tilemap_table(rom, state, num_frames);
 
@@ -47,44 +91,391 @@ pub fn graphics(rom: &Rom, state: usize, num_frames: usize) -> Vec<Vec<Tile>> {
     data.into_iter().map(|(t, b)| generate_graphics(rom, t, b)).collect()
 }
 
+/// The palettes a suit's afterimages cycle through for an effect like
+/// `speed_booster`, `shinespark` or `screw_attack`, newest echo first
+pub fn echo_palettes(rom: &Rom, suit: &str, effect: &str) -> Vec<Vec<u16>> {
+    let prefix = format!("{}_{}_", suit, effect);
+    palettes(rom).into_iter()
+        .filter(|(name, _)| name.starts_with(&prefix))
+        .map(|(_, colors)| colors)
+        .collect()
+}
+
+pub struct Cannon {
+    /// 0 is up facing right, going clockwise to 9, up facing left
+    pub direction: u8,
+    /// Drawn under Samus instead of on top of her
+    pub behind: bool,
+    /// Offset from Samus's center for each animation frame
+    pub positions: Vec<(i16, i16)>,
+}
+
+/// The arm cannon for a pose, if it shows one at all
+pub fn cannon(rom: &Rom, state: usize, num_frames: usize) -> Option<Cannon> {
+    let pointer = LittleEndian::read_u16(rom.read(CANNON_POSITION_POINTERS.to_pc() + state * 2, 2)) as u32;
+    if pointer == 0 {
+        return None;
+    }
+    let entry = rom.read(SnesAddress(CANNON_POSITION_BANK | pointer).to_pc(), 2 + num_frames * 2);
+    if entry[0] >= NUM_CANNON_DIRECTIONS {
+        return None;
+    }
+    Some(Cannon {
+        direction: entry[0],
+        behind: entry[1] & 1 > 0,
+        positions: entry[2..].chunks(2).map(|p| (p[0] as i8 as i16, p[1] as i8 as i16)).collect(),
+    })
+}
+
+pub fn cannon_tile(rom: &Rom, direction: u8) -> Tile {
+    let pointer = LittleEndian::read_u16(rom.read(CANNON_TILE_POINTERS.to_pc() + direction as usize * 2, 2)) as u32;
+    Bitplanes::new(rom.read(SnesAddress(CANNON_TILE_BANK | pointer).to_pc(), 32))
+        .next()
+        .unwrap_or_default()
+}
+
+/// Adds the arm cannon to the frames from `tilemaps` and `graphics`, as one
+/// more part and one more tile per frame. Poses without a cannon are left alone.
+pub fn add_cannon(rom: &Rom, state: usize, tilemaps: &mut [Vec<FrameMap>], graphics: &mut [Vec<Tile>]) {
+    let cannon = match cannon(rom, state, tilemaps.len()) {
+        Some(cannon) => cannon,
+        None => return,
+    };
+    let tile = cannon_tile(rom, cannon.direction);
+    // The same tiles point either way, flipped when Samus faces left
+    let flip = pose_definition(rom, state).direction == Direction::Left;
+    for ((maps, tiles), &(x, y)) in tilemaps.iter_mut().zip(graphics.iter_mut()).zip(cannon.positions.iter()) {
+        // Drawn with Samus's own palette
+        let palette = maps.first().map(FrameMap::palette).unwrap_or(0);
+        let part = FrameMap::single(x, y, tiles.len() as u8, flip, palette);
+        tiles.push(tile);
+        // Parts are drawn last to first
+        if cannon.behind {
+            maps.push(part);
+        } else {
+            maps.insert(0, part);
+        }
+    }
+}
+
+/// Every Samus palette, named like `power`, `varia_charge_3` or `visor_0`.
+/// Effects that animate (charging, speed booster...) get one palette per
+/// frame of the glow.
+pub fn palettes(rom: &Rom) -> Vec<(String, Vec<u16>)> {
+    let mut palettes = Vec::new();
+    for table in PALETTE_TABLES.iter() {
+        let suits: &[&str] = if table.per_suit { &SUITS } else { &[""] };
+        for (suit_index, suit) in suits.iter().enumerate() {
+            for frame in 0..table.frames {
+                let entry = table.pointers.to_pc() + (suit_index * table.frames + frame) * 2;
+                let pointer = LittleEndian::read_u16(rom.read(entry, 2)) as u32;
+                let colors = rom.read(SnesAddress(PALETTE_BANK | pointer).to_pc(), 32)
+                    .chunks(2)
+                    .map(LittleEndian::read_u16)
+                    .collect();
+                let mut name: Vec<String> = Vec::new();
+                if !suit.is_empty() {
+                    name.push(suit.to_string());
+                }
+                if !table.name.is_empty() {
+                    name.push(table.name.to_string());
+                }
+                if table.frames > 1 {
+                    name.push(frame.to_string());
+                }
+                palettes.push((name.join("_"), colors));
+            }
+        }
+    }
+    palettes
+}
+
+/// What the game does with Samus while she's in a pose ($0A1F)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Movement {
+    Standing,
+    Running,
+    NormalJumping,
+    SpinJumping,
+    MorphBallOnGround,
+    Crouching,
+    Falling,
+    MorphBallFalling,
+    Knockback,
+    TurningAround,
+    Transitioning,
+    Moonwalking,
+    SpringBallOnGround,
+    SpringBallInAir,
+    SpringBallFalling,
+    WallJumping,
+    RanIntoWall,
+    Grappling,
+    TurningAroundJumping,
+    TurningAroundFalling,
+    DamageBoost,
+    GrabbedByDraygon,
+    Shinespark,
+    Other(u8),
+}
+
+impl Movement {
+    fn name(&self) -> &'static str {
+        match *self {
+            Movement::Standing => "standing",
+            Movement::Running => "running",
+            Movement::NormalJumping => "jumping",
+            Movement::SpinJumping => "spin_jumping",
+            Movement::MorphBallOnGround => "morphball",
+            Movement::Crouching => "crouching",
+            Movement::Falling => "falling",
+            Movement::MorphBallFalling => "morphball_falling",
+            Movement::Knockback => "knockback",
+            Movement::TurningAround => "turning",
+            Movement::Transitioning => "transitioning",
+            Movement::Moonwalking => "moonwalking",
+            Movement::SpringBallOnGround => "springball",
+            Movement::SpringBallInAir => "springball_jumping",
+            Movement::SpringBallFalling => "springball_falling",
+            Movement::WallJumping => "wall_jumping",
+            Movement::RanIntoWall => "ran_into_wall",
+            Movement::Grappling => "grappling",
+            Movement::TurningAroundJumping => "turning_jumping",
+            Movement::TurningAroundFalling => "turning_falling",
+            Movement::DamageBoost => "damage_boost",
+            Movement::GrabbedByDraygon => "grabbed_by_draygon",
+            Movement::Shinespark => "shinespark",
+            Movement::Other(_) => "unknown",
+        }
+    }
+
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            0x00 => Movement::Standing,
+            0x01 => Movement::Running,
+            0x02 => Movement::NormalJumping,
+            0x03 => Movement::SpinJumping,
+            0x04 => Movement::MorphBallOnGround,
+            0x05 => Movement::Crouching,
+            0x06 => Movement::Falling,
+            0x08 => Movement::MorphBallFalling,
+            0x0A => Movement::Knockback,
+            0x0E => Movement::TurningAround,
+            0x0F => Movement::Transitioning,
+            0x10 => Movement::Moonwalking,
+            0x11 => Movement::SpringBallOnGround,
+            0x12 => Movement::SpringBallInAir,
+            0x13 => Movement::SpringBallFalling,
+            0x14 => Movement::WallJumping,
+            0x15 => Movement::RanIntoWall,
+            0x16 => Movement::Grappling,
+            0x17 => Movement::TurningAroundJumping,
+            0x18 => Movement::TurningAroundFalling,
+            0x19 => Movement::DamageBoost,
+            0x1A => Movement::GrabbedByDraygon,
+            0x1B => Movement::Shinespark,
+            b => Movement::Other(b),
+        }
+    }
+}
+
+/// An entry in the pose definition table at $91:B629
+#[derive(Copy, Clone, Debug)]
+pub struct PoseDefinition {
+    pub direction: Direction,
+    pub movement: Movement,
+    /// Pose to switch to when Samus hits something, if any
+    pub new_pose: Option<u8>,
+    pub cannon_direction: u8,
+    pub y_offset: i8,
+    pub y_radius: u8,
+}
+
+impl PoseDefinition {
+    /// Left, top, right, bottom, inclusive, relative to Samus's center
+    pub fn hitbox(&self) -> (i16, i16, i16, i16) {
+        let r = self.y_radius as i16;
+        (-X_RADIUS, -r, X_RADIUS - 1, r - 1)
+    }
+
+    /// How far down to move the frame's origin so that Samus's feet land on
+    /// the same line no matter how tall the pose is: how much lower her feet
+    /// are than when standing, negative for anything shorter.
+    pub fn v_offset(&self) -> i16 {
+        self.y_radius as i16 + self.y_offset as i16 - STANDING_Y_RADIUS
+    }
+}
+
+pub fn pose_definition(rom: &Rom, state: usize) -> PoseDefinition {
+    let entry = rom.read(POSE_DEFINITIONS.to_pc() + state * POSE_DEFINITION_SIZE, POSE_DEFINITION_SIZE);
+    PoseDefinition {
+        direction: match entry[0] {
+            0x04 => Direction::Left,
+            0x08 => Direction::Right,
+            _ => Direction::Forward,
+        },
+        movement: Movement::from_byte(entry[1]),
+        new_pose: if entry[2] == 0xFF { None } else { Some(entry[2]) },
+        cannon_direction: entry[3],
+        y_offset: entry[4] as i8,
+        y_radius: entry[6],
+    }
+}
+
+/// A pose that exists in the ROM, with a name made up from its definition
+pub struct PoseInfo {
+    pub state: usize,
+    pub name: String,
+    /// Where to go when no buttons are held, if the ROM's own transitions
+    /// don't already say
+    pub default_state: Option<usize>,
+    pub definition: PoseDefinition,
+}
+
+/// Lists every pose with an animation, straight from the ROM's tables
+pub fn poses(rom: &Rom) -> Vec<PoseInfo> {
+    let definitions: Vec<_> = (0..NUM_POSES).map(|state| pose_definition(rom, state)).collect();
+    let transitions: Vec<_> = (0..NUM_POSES).map(|state| lookup_pose_transitions(rom, state)).collect();
+    (0..NUM_POSES).filter_map(|state| {
+        if lookup_frame_sequence(rom, state).0.is_empty() {
+            return None;
+        }
+        let definition = definitions[state];
+        Some(PoseInfo {
+            state,
+            name: pose_name(state, &definition),
+            default_state: default_state(state, &definitions, &transitions),
+            definition,
+        })
+    }).collect()
+}
+
+fn pose_name(state: usize, definition: &PoseDefinition) -> String {
+    let facing = match definition.direction {
+        Direction::Forward => "facing_forward",
+        Direction::Left => "facing_left",
+        Direction::Right => "facing_right",
+    };
+    let aim = match definition.cannon_direction & 0x0F {
+        0x00 | 0x09 => "_aiming_up",
+        0x01 => "_aiming_upright",
+        0x03 => "_aiming_downright",
+        0x04 | 0x05 => "_aiming_down",
+        0x06 => "_aiming_downleft",
+        0x08 => "_aiming_upleft",
+        _ => "",
+    };
+    format!("{}_{}{}_{:02x}", definition.movement.name(), facing, aim, state)
+}
+
+fn is_aiming(definition: &PoseDefinition) -> bool {
+    !matches!(definition.cannon_direction & 0x0F, 0x02 | 0x07)
+}
+
+// Letting go of everything in mid-air isn't in the transition table, the
+// game just leaves Samus aiming wherever she was. Drifting on while holding
+// only the way she faces is though, and that's the plain jump or fall to go
+// back to.
+fn airborne_default(state: usize, definitions: &[PoseDefinition], transitions: &[Vec<Transition>]) -> Option<usize> {
+    let definition = &definitions[state];
+    match definition.movement {
+        Movement::NormalJumping | Movement::SpinJumping | Movement::Falling | Movement::WallJumping => (),
+        _ => return None,
+    }
+    let facing = match definition.direction {
+        Direction::Left => ControllerInput::Left,
+        Direction::Right => ControllerInput::Right,
+        Direction::Forward => return None,
+    };
+    transitions[state].iter()
+        .filter(|t| t.pressed.is_empty() && t.held == facing)
+        .map(|t| t.to_pose as usize)
+        .find(|&to| to != state && to < definitions.len() &&
+            definitions[to].direction == definition.direction && !is_aiming(&definitions[to]))
+}
+
+// A pose you only get into by holding buttons goes back to where you were
+// when you let go of them: the pose facing the same way, not aiming, whose
+// transitions lead here with nothing newly pressed. One with the same
+// movement type wins, then the lowest numbered.
+fn default_state(state: usize, definitions: &[PoseDefinition], transitions: &[Vec<Transition>]) -> Option<usize> {
+    if is_aiming(&definitions[state]) {
+        if let Some(default) = airborne_default(state, definitions, transitions) {
+            return Some(default);
+        }
+    }
+    if transitions[state].iter().any(|t| t.pressed.is_empty() && t.held.is_empty()) {
+        return None;
+    }
+    let definition = &definitions[state];
+    if definition.direction == Direction::Forward {
+        return None;
+    }
+    let candidates: Vec<usize> = (0..definitions.len())
+        .filter(|&from| from != state)
+        .filter(|&from| definitions[from].direction == definition.direction && !is_aiming(&definitions[from]))
+        .filter(|&from| transitions[from].iter().any(|t| t.to_pose as usize == state && t.pressed.is_empty() && !t.held.is_empty()))
+        .collect();
+    candidates.iter().cloned()
+        .find(|&from| definitions[from].movement == definition.movement)
+        .or_else(|| candidates.first().cloned())
+}
+
 pub struct Sequence<'a>(pub &'a [u8], pub Terminator, pub Vec<Transition>);
 
 pub fn lookup_frame_sequence<'a>(rom: &'a Rom, state: usize) -> Sequence<'a> {
-    let addr = LittleEndian::read_u16(&rom.read(FRAME_DURATION_TABLE.to_pc() + state * 2, 2)) as u32;
-    let mut len = 0;
-    let mut term = Terminator::Loop;
-    for bytes in rom[(FRAME_DURATION_START + addr).to_pc()..].windows(2) {
-        if bytes[0] >= 0xF0 {
-            term = match bytes[0] {
-                0xFF => Terminator::Loop,
-                0xFE => Terminator::Backtrack(bytes[1]),
-                0xFD => Terminator::TransitionTo(bytes[1]), // possibly a second extra byte of data
-                0xFB => Terminator::Loop, // wall jump ??
-                0xF9 => Terminator::Loop, // unsure, possibly 6 more bytes though
-                0xF8 => Terminator::TransitionTo(bytes[1]),
-                0xF6 => Terminator::Loop, // heavy breathing ??
-                0xF0 => Terminator::Stop,
-                0xF1 | 0xF2 | 0xF3 | 0xF4 | 0xF5 | 0xF7 | 0xFA | 0xFC => Terminator::Loop,
-                _ => unreachable!(),
-            };
-            break;
-        };
-        len += 1;
-    };
+    let addr = LittleEndian::read_u16(rom.read(FRAME_DURATION_TABLE.to_pc() + state * 2, 2)) as u32;
+    let start = (FRAME_DURATION_START + addr).to_pc();
+    let len = rom[start..].iter().take_while(|&&b| b < 0xF0).count();
+    let term = read_terminator(&rom[start + len..]);
     let transitions = lookup_pose_transitions(rom, state);
-    Sequence(&rom.read((FRAME_DURATION_START + addr).to_pc(), len), term, transitions)
+    Sequence(rom.read(start, len), term, transitions)
 }
 
-pub fn lookup_pose_transitions<'a>(rom: &'a Rom, state: usize) -> Vec<Transition> {
-    let offset = LittleEndian::read_u16(&rom.read(POSE_TRANSITION_TABLE.to_pc() + state * 2, 2)) as u32;
+/// Decodes the instruction at the end of a frame delay list, along with
+/// however many argument bytes it takes. FD only ever has the one byte; what
+/// looked like a second one is the start of the next pose's list.
+fn read_terminator(bytes: &[u8]) -> Terminator {
+    let byte = |n: usize| bytes[n];
+    let word = |n: usize| LittleEndian::read_u16(&bytes[n..n + 2]);
+    match byte(0) {
+        0xFF => Terminator::Loop,
+        0xFE => Terminator::Backtrack(byte(1)),
+        0xFD => Terminator::TransitionTo(byte(1)),
+        0xFC => Terminator::TransitionOnItem {
+            items: word(1),
+            with: byte(3),
+            without: byte(4),
+        },
+        0xFB => Terminator::WallJump,
+        0xFA => Terminator::TransitionOnDirection {
+            up: byte(1),
+            down: byte(2),
+        },
+        0xF9 => Terminator::TransitionOnItemAndDirection {
+            items: word(1),
+            with_up: byte(3),
+            with_down: byte(4),
+            without_up: byte(5),
+            without_down: byte(6),
+        },
+        0xF8 => Terminator::AutoJump(byte(1)),
+        0xF6 => Terminator::HeavyBreathing,
+        0xF0 => Terminator::Stop,
+        op @ 0xF1..=0xF7 => Terminator::Unused(op),
+        _ => unreachable!(),
+    }
+}
+
+pub fn lookup_pose_transitions(rom: &Rom, state: usize) -> Vec<Transition> {
+    let offset = LittleEndian::read_u16(rom.read(POSE_TRANSITION_TABLE.to_pc() + state * 2, 2)) as u32;
     let addr = (FRAME_DURATION_START + offset).to_pc();
     rom[addr..].chunks(6).take_while(|chunk| chunk[0] != 0xFF && chunk[1] != 0xFF).map(|slice| {
-        let controls_1 = LittleEndian::read_u16(&slice[0..2]);
-        let controls_2 = LittleEndian::read_u16(&slice[2..4]);
-        let input = ControllerInput::from_bits_truncate(controls_1 | controls_2);
+        let pressed = ControllerInput::from_bits_truncate(LittleEndian::read_u16(&slice[0..2]));
+        let held = ControllerInput::from_bits_truncate(LittleEndian::read_u16(&slice[2..4]));
         let to_pose = LittleEndian::read_u16(&slice[4..6]) as u8;
 
-        Transition { input: input, to_pose }
+        Transition { pressed, held, to_pose }
     }).collect()
 }
 
@@ -92,24 +483,24 @@ fn lookup_tilemap_table<'a>(rom: &'a Rom, state: usize, num_frames: usize) -> (&
     let bottom_half = BOTTOM_HALF_POINTERS.to_pc() + state * 2;
     let top_half = TOP_HALF_POINTERS.to_pc() + state * 2;
     let base_addr = BASE_TABLES_POINTER.to_pc();
-    let b = base_addr + LittleEndian::read_u16(&rom.read(bottom_half, 2)) as usize * 2;
-    let t = base_addr + LittleEndian::read_u16(&rom.read(top_half, 2)) as usize * 2;
-    (&rom.read(b, num_frames * 2), &rom.read(t, num_frames * 2))
+    let b = base_addr + LittleEndian::read_u16(rom.read(bottom_half, 2)) as usize * 2;
+    let t = base_addr + LittleEndian::read_u16(rom.read(top_half, 2)) as usize * 2;
+    (rom.read(b, num_frames * 2), rom.read(t, num_frames * 2))
 }
 
 fn lookup_frame_dma_pointers<'a>(rom: &'a Rom, state: usize, num_frames: usize) -> &'a [u8] {
     let lookup_addr = FRAME_PROGRESSION_TABLE_LOOKUP.to_pc() + state * 2;
-    let offset = LittleEndian::read_u16(&rom.read(lookup_addr, 2)) as usize;
+    let offset = LittleEndian::read_u16(rom.read(lookup_addr, 2)) as usize;
     let addr = FRAME_PROGRESSION_TABLES.to_pc() + offset;
-    &rom.read(addr, num_frames * 4)
+    rom.read(addr, num_frames * 4)
 }
 
 type DmaEntry = (PcAddress, usize, usize);
 
 fn read_dma(rom: &Rom, table_pointer: PcAddress, entry: u8) -> DmaEntry {
-    let dma_offset = LittleEndian::read_u16(&rom.read(table_pointer, 2)) as usize;
+    let dma_offset = LittleEndian::read_u16(rom.read(table_pointer, 2)) as usize;
     let entry_offset = FRAME_PROGRESSION_TABLES.to_pc() + dma_offset + entry as usize * 7;
-    let slice = &rom.read(entry_offset, 7);
+    let slice = rom.read(entry_offset, 7);
     let snes_graphics_addr = LittleEndian::read_u24(&slice[0..3]);
     let graphics_addr = SnesAddress(snes_graphics_addr).to_pc();
     let part_1_bytes = LittleEndian::read_u16(&slice[3..5]) as usize;
@@ -133,7 +524,7 @@ fn read_bottom_dma(rom: &Rom, index: u8, entry: u8) -> DmaEntry {
 }
 
 fn lookup_graphics_data(rom: &Rom, pointer_entries: &[u8]) -> Vec<(DmaEntry, DmaEntry)> {
-    assert!(pointer_entries.len() % 4 == 0, "Frame progression is not evenly divisible by 4 bytes");
+    assert!(pointer_entries.len().is_multiple_of(4), "Frame progression is not evenly divisible by 4 bytes");
     pointer_entries.chunks(4).map(|frame| {
         let top_dma_table = frame[0];
         let top_dma_entry = frame[1];
@@ -179,13 +570,66 @@ fn generate_graphics(rom: &Rom, top_frame: DmaEntry, bottom_frame: DmaEntry) ->
     let bottom_part2_padding = (0..((HALF_ROW - bottom_frame.2) / 32)).map(|_| Tile::default());
 
 
-    Bitplanes::new(&rom.read(top_frame.0, top_frame.1))
+    Bitplanes::new(rom.read(top_frame.0, top_frame.1))
     .chain(top_part1_padding)
-    .chain(Bitplanes::new(&rom.read(bottom_frame.0, bottom_frame.1)))
+    .chain(Bitplanes::new(rom.read(bottom_frame.0, bottom_frame.1)))
     .chain(bottom_part1_padding)
-    .chain(Bitplanes::new(&rom.read(top_frame.0 + top_frame.1, top_frame.2)))
+    .chain(Bitplanes::new(rom.read(top_frame.0 + top_frame.1, top_frame.2)))
     .chain(top_part2_padding)
-    .chain(Bitplanes::new(&rom.read(bottom_frame.0 + bottom_frame.1, bottom_frame.2)))
+    .chain(Bitplanes::new(rom.read(bottom_frame.0 + bottom_frame.1, bottom_frame.2)))
     .chain(bottom_part2_padding)
     .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{default_state, ControllerInput, Direction, Movement, PoseDefinition, Transition};
+    use frame_map::FrameMap;
+    use snes_bitplanes::Tile;
+
+    fn definition(movement: Movement, y_radius: u8, y_offset: i8) -> PoseDefinition {
+        PoseDefinition {
+            direction: Direction::Right,
+            movement,
+            new_pose: None,
+            cannon_direction: 0x02,
+            y_offset,
+            y_radius,
+        }
+    }
+
+    #[test]
+    fn aiming_in_the_air_goes_back_to_drifting() {
+        let aiming_up = PoseDefinition { cannon_direction: 0x00, ..definition(Movement::NormalJumping, 0x15, 0) };
+        let definitions = [aiming_up, definition(Movement::NormalJumping, 0x15, 0), definition(Movement::NormalJumping, 0x15, 0)];
+        let transition = |pressed, held, to_pose| Transition { pressed, held, to_pose };
+        let transitions = vec![
+            vec![transition(ControllerInput::empty(), ControllerInput::Right, 2)],
+            vec![transition(ControllerInput::empty(), ControllerInput::Up, 0)],
+            vec![transition(ControllerInput::empty(), ControllerInput::Up, 0)],
+        ];
+        assert_eq!(default_state(0, &definitions, &transitions), Some(2));
+        // Standing still, nothing says which way to drift
+        let transitions = vec![vec![], transitions[1].clone(), transitions[2].clone()];
+        assert_eq!(default_state(0, &definitions, &transitions), Some(1));
+    }
+
+    #[test]
+    fn v_offset_follows_the_feet() {
+        let poses = [
+            (definition(Movement::Standing, 0x15, 0), 0),
+            (definition(Movement::Crouching, 0x10, 0), -5),
+            (definition(Movement::MorphBallOnGround, 0x07, 0), -14),
+            (definition(Movement::SpinJumping, 0x0E, 0), -7),
+            (definition(Movement::Crouching, 0x10, 3), -2),
+        ];
+        for &(ref pose, v_offset) in poses.iter() {
+            assert_eq!(pose.v_offset(), v_offset, "{:?}", pose);
+            // A tile resting on the pose's feet ends up on standing's feet
+            let (_, _, _, bottom) = pose.hitbox();
+            let part = FrameMap::single(0, bottom + pose.y_offset as i16 - 7, 0, false, 0);
+            let frame = FrameMap::composite(&[part], &[Tile([1; 64])], 1, pose.v_offset());
+            assert_eq!(frame.height as i16 - frame.zero_y as i16, 0x15, "{:?}", pose);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use std::ops::{Add, Index, Range, RangeFrom, RangeTo};
-use std::fmt;
+use std::{cmp, fmt};
 
 // #[inline(always)]
 // pub fn snespc(bank: u8, addr: u16) -> PcAddress {
@@ -22,6 +22,18 @@ impl<'a> Rom<'a> {
         &self.0[addr.0 .. addr.0 + len]
     }
 
+    /// A 16 bit count followed by that many `entry_size` byte entries. Bad
+    /// pointers and garbage counts get cut short at the end of the ROM
+    /// instead of reading past it.
+    pub fn read_counted(&self, addr: PcAddress, entry_size: usize) -> &'a [u8] {
+        let rest = self.0.get(addr.0 + 2..).unwrap_or(&[]);
+        let count = match self.0.get(addr.0..addr.0 + 2) {
+            Some(count) => count[0] as usize | (count[1] as usize) << 8,
+            None => 0,
+        };
+        &rest[..cmp::min(count, rest.len() / entry_size) * entry_size]
+    }
+
     pub fn read_string(&self, addr: PcAddress, max_len: usize) -> Option<String> {
         let mut v = Vec::new();
         for c in self.0[addr.0.. addr.0 + max_len].iter().take_while(|c| **c != 0x20 && **c != 0x00) {
@@ -98,9 +110,9 @@ impl Add<u32> for SnesAddress {
     }
 }
 
-impl Into<PcAddress> for SnesAddress {
-    fn into(self) -> PcAddress {
-        PcAddress(snespc(self.0))
+impl From<SnesAddress> for PcAddress {
+    fn from(addr: SnesAddress) -> PcAddress {
+        PcAddress(snespc(addr.0))
     }
 }
 
@@ -1,56 +1,123 @@
-// use std::cmp;
-use util::{bgr555_rgb888, bgr555_rgbf32};
+use std::cmp;
+use util::{palette_rows, ColorConversion};
 use frame_map::CompositedFrame;
+use echo::{self, Echo};
 
 pub struct Sprite<'a> {
     frames: Vec<CompositedFrame>,
     palette: &'a [u16],
+    conversion: ColorConversion,
 }
 
 impl<'a> Sprite<'a> {
     pub fn new(frames: Vec<CompositedFrame>, palette: &'a [u16]) -> Self {
         Sprite {
-            frames: frames,
-            palette: palette,
+            frames,
+            palette,
+            conversion: ColorConversion::default(),
         }
     }
 
-    // pub fn width(&self) -> u16 {
-    //     let (l, r) = self.frames.iter().fold((0, 0), |(l, r), f| {
-    //         (cmp::max(l, f.zero_x), cmp::max(r, f.width - f.zero_x)) // SHOW ME YOUR MOVES
-    //     });
-    //     l + r
-    // }
+    /// How `palette888` and `palettef32`, and everything exported from this
+    /// sprite, turn SNES colors into RGB
+    pub fn with_color_conversion(mut self, conversion: ColorConversion) -> Self {
+        self.conversion = conversion;
+        self
+    }
+
+    pub fn color_conversion(&self) -> ColorConversion {
+        self.conversion
+    }
+
+    /// Wide enough for every frame once their origins are lined up
+    pub fn width(&self) -> u16 {
+        let (l, r) = self.frames.iter().fold((0, 0), |(l, r), f| {
+            (cmp::max(l, f.zero_x), cmp::max(r, f.width.saturating_sub(f.zero_x))) // SHOW ME YOUR MOVES
+        });
+        l + r
+    }
 
-    // pub fn height(&self) -> u16 {
-    //     let (t, b) = self.frames.iter().fold((0, 0), |(t, b), f| {
-    //         (cmp::max(t, f.zero_y), cmp::max(b, f.height - f.zero_y))
-    //     });
-    //     t + b
-    // }
+    pub fn height(&self) -> u16 {
+        let (t, b) = self.frames.iter().fold((0, 0), |(t, b), f| {
+            (cmp::max(t, f.zero_y), cmp::max(b, f.height.saturating_sub(f.zero_y)))
+        });
+        t + b
+    }
 
-    // pub fn zero(&self) -> (u16, u16) {
-    //     self.frames.iter().fold((0, 0), |(x, y), f| {
-    //         (cmp::max(x, f.zero_x), cmp::max(y, f.zero_y))
-    //     })
-    // }
+    /// The shared origin on the `width` x `height` canvas
+    pub fn zero(&self) -> (u16, u16) {
+        self.frames.iter().fold((0, 0), |(x, y), f| {
+            (cmp::max(x, f.zero_x), cmp::max(y, f.zero_y))
+        })
+    }
+
+    /// Every frame redrawn on the same size canvas with the same origin, so
+    /// they can be played back or exported without jittering around
+    pub fn canvas_frames(&self) -> Vec<CompositedFrame> {
+        let (width, height, zero) = (self.width(), self.height(), self.zero());
+        self.frames.iter().map(|f| f.on_canvas(width, height, zero)).collect()
+    }
+
+    pub fn palette(&self) -> &'a [u16] {
+        self.palette
+    }
 
     pub fn frames(&self) -> &[CompositedFrame] {
         &self.frames
     }
 
     pub fn palette888(&self) -> Vec<(u8, u8, u8)> {
-        self.palette.iter().map(bgr555_rgb888).collect()
+        palette_rows(self.palette).iter().map(|c| self.conversion.rgb888(c)).collect()
     }
 
     pub fn palettef32(&self) -> Vec<(f32, f32, f32)> {
-        self.palette.iter().map(bgr555_rgbf32).collect()
+        palette_rows(self.palette).iter().map(|c| self.conversion.rgbf32(c)).collect()
+    }
+
+    /// Every frame with the frames before it trailing behind, looping back
+    /// round to the last ones for the first. The `k`th echo back is drawn
+    /// `k` times `step` away with `palettes[k - 1]`, as many as fit in the
+    /// palette (see `echo::max_echoes`). Returns the frames and the palette
+    /// they all share.
+    pub fn echo_trails(&self, step: (i16, i16), palettes: &[&[u16]]) -> (Vec<CompositedFrame>, Vec<u16>) {
+        let len = self.frames.len();
+        let rows = echo::rows_used(&self.frames);
+        let num_echoes = cmp::min(palettes.len(), echo::max_echoes(rows.len()));
+        let mut colors = self.palette.to_vec();
+        let trails = (0..len).map(|i| {
+            let echoes: Vec<_> = (1..num_echoes + 1).rev().map(|k| Echo {
+                frame: &self.frames[(i + len * k - k) % len],
+                position: (step.0 * k as i16, step.1 * k as i16),
+                palette: palettes[k - 1],
+            }).collect();
+            let (frame, trail_colors) = echo::trail(&self.frames[i], self.palette, &echoes, &rows);
+            colors = trail_colors;
+            frame
+        }).collect();
+        (trails, colors)
     }
 }
 
+/// How a `SpriteView` moves through its frames
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Playback {
+    Forward,
+    Reverse,
+    /// Forward to the last frame, then back to the first, and again
+    PingPong,
+}
+
+/// Plays a sprite back in 60Hz ticks, the unit `CompositedFrame::duration`
+/// is counted in, so the timing doesn't depend on how often it gets drawn.
 pub struct SpriteView<'a> {
     frames: &'a [CompositedFrame],
     index: usize,
+    // Ticks spent on the current frame so far
+    elapsed: u32,
+    playback: Playback,
+    // Which way ping-pong is going right now
+    backwards: bool,
+    paused: bool,
 }
 
 impl<'a> SpriteView<'a> {
@@ -58,12 +125,293 @@ impl<'a> SpriteView<'a> {
         SpriteView {
             frames: sprite.frames(),
             index: 0,
+            elapsed: 0,
+            playback: Playback::Forward,
+            backwards: false,
+            paused: false,
         }
     }
 
-    pub fn frame(&mut self) -> &'a CompositedFrame {
-        let f = &self.frames[self.index as usize];
-        self.index = (self.index + 1) % self.frames.len();
+    /// Returns the current frame and moves on to the next one, ignoring durations
+    pub fn frame(&mut self) -> Option<&'a CompositedFrame> {
+        let f = self.current();
+        self.step();
+        self.elapsed = 0;
         f
     }
+
+    /// `None` if the sprite doesn't have any frames
+    pub fn current(&self) -> Option<&'a CompositedFrame> {
+        self.frames.get(self.index)
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Lets `ticks` 60Hz ticks go by and returns whatever frame is showing after
+    pub fn tick(&mut self, ticks: u32) -> Option<&'a CompositedFrame> {
+        if !self.paused && !self.frames.is_empty() {
+            // Whole trips through the animation end up right back here
+            self.elapsed = self.elapsed.saturating_add(ticks % self.period());
+            while self.elapsed >= self.duration() {
+                self.elapsed -= self.duration();
+                self.step();
+            }
+        }
+        self.current()
+    }
+
+    /// Jumps to `tick` ticks after the start of the first frame, wrapping
+    /// around the length of the animation
+    pub fn seek(&mut self, tick: u32) {
+        let total: u32 = (0..self.frames.len()).map(|n| self.duration_of(n)).sum();
+        if total == 0 {
+            return;
+        }
+        let mut tick = tick % total;
+        self.index = 0;
+        while tick >= self.duration_of(self.index) {
+            tick -= self.duration_of(self.index);
+            self.index += 1;
+        }
+        self.elapsed = tick;
+        self.backwards = false;
+    }
+
+    /// Moves one frame on, whichever way things are playing, without
+    /// turning ping-pong around
+    pub fn step_forward(&mut self) {
+        let len = self.frames.len();
+        if len > 0 {
+            self.index = (self.index + 1) % len;
+            self.elapsed = 0;
+        }
+    }
+
+    pub fn step_back(&mut self) {
+        let len = self.frames.len();
+        if len > 0 {
+            self.index = (self.index + len - 1) % len;
+            self.elapsed = 0;
+        }
+    }
+
+    pub fn set_playback(&mut self, playback: Playback) {
+        self.playback = playback;
+        self.backwards = playback == Playback::Reverse;
+    }
+
+    pub fn playback(&self) -> Playback {
+        self.playback
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Ticks until the animation is back where it started. Ping-pong plays
+    // everything but the first and last frames twice.
+    fn period(&self) -> u32 {
+        let len = self.frames.len();
+        let total: u32 = (0..len).map(|n| self.duration_of(n)).sum();
+        match self.playback {
+            Playback::PingPong if len > 1 => 2 * total - self.duration_of(0) - self.duration_of(len - 1),
+            _ => total,
+        }
+    }
+
+    // Zero length frames would never let `tick` finish
+    fn duration_of(&self, index: usize) -> u32 {
+        cmp::max(self.frames[index].duration as u32, 1)
+    }
+
+    fn duration(&self) -> u32 {
+        self.duration_of(self.index)
+    }
+
+    fn step(&mut self) {
+        let len = self.frames.len();
+        if len <= 1 {
+            return;
+        }
+        match self.playback {
+            Playback::Forward => self.index = (self.index + 1) % len,
+            Playback::Reverse => self.index = (self.index + len - 1) % len,
+            Playback::PingPong => {
+                if self.backwards && self.index == 0 {
+                    self.backwards = false;
+                } else if !self.backwards && self.index == len - 1 {
+                    self.backwards = true;
+                }
+                self.index = if self.backwards { self.index - 1 } else { self.index + 1 };
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Playback, Sprite, SpriteView};
+    use frame_map::CompositedFrame;
+
+    fn frames(durations: &[u16]) -> Vec<CompositedFrame> {
+        durations.iter().map(|&duration| CompositedFrame {
+            buffer: vec![],
+            priority: vec![],
+            width: 0,
+            height: 0,
+            zero_x: 0,
+            zero_y: 0,
+            duration,
+        }).collect()
+    }
+
+    #[test]
+    fn ticks_follow_durations() {
+        let sprite = Sprite::new(frames(&[2, 3, 1]), &[]);
+        let mut view = SpriteView::new(&sprite);
+        view.tick(1);
+        assert_eq!(view.index(), 0);
+        view.tick(1);
+        assert_eq!(view.index(), 1);
+        view.tick(4);
+        assert_eq!(view.index(), 0);
+        view.seek(5);
+        assert_eq!(view.index(), 2);
+    }
+
+    #[test]
+    fn reverse_and_ping_pong() {
+        let sprite = Sprite::new(frames(&[1, 1, 1]), &[]);
+        let mut view = SpriteView::new(&sprite);
+        view.set_playback(Playback::Reverse);
+        view.tick(1);
+        assert_eq!(view.index(), 2);
+
+        view.seek(0);
+        view.set_playback(Playback::PingPong);
+        let order: Vec<_> = (0..6).map(|_| { view.tick(1); view.index() }).collect();
+        assert_eq!(order, vec![1, 2, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn huge_ticks_wrap_around() {
+        let sprite = Sprite::new(frames(&[2, 3, 1]), &[]);
+        let mut view = SpriteView::new(&sprite);
+        // u32::MAX is 3 more than a multiple of 6
+        view.tick(u32::MAX);
+        assert_eq!(view.index(), 1);
+        view.tick(u32::MAX);
+        assert_eq!(view.index(), 0);
+
+        let sprite = Sprite::new(frames(&[1, 1, 1]), &[]);
+        let mut view = SpriteView::new(&sprite);
+        view.set_playback(Playback::PingPong);
+        // 0, 1, 2, 1 and round again
+        view.tick(u32::MAX);
+        assert_eq!(view.index(), 1);
+        view.tick(1);
+        assert_eq!(view.index(), 0);
+    }
+
+    #[test]
+    fn paused_views_stay_put() {
+        let sprite = Sprite::new(frames(&[1, 1]), &[]);
+        let mut view = SpriteView::new(&sprite);
+        view.pause();
+        view.tick(10);
+        assert_eq!(view.index(), 0);
+        view.resume();
+        view.tick(1);
+        assert_eq!(view.index(), 1);
+    }
+
+    #[test]
+    fn stepping_keeps_ping_pong_going() {
+        let sprite = Sprite::new(frames(&[1, 1, 1]), &[]);
+        let mut view = SpriteView::new(&sprite);
+        view.set_playback(Playback::PingPong);
+        view.tick(3);
+        assert_eq!(view.index(), 1);
+        view.step_forward();
+        assert_eq!(view.index(), 2);
+        view.step_back();
+        view.step_back();
+        assert_eq!(view.index(), 0);
+        // Still on the way back, so it bounces off the first frame
+        view.tick(1);
+        assert_eq!(view.index(), 1);
+
+        let empty = Sprite::new(vec![], &[]);
+        let mut view = SpriteView::new(&empty);
+        view.step_forward();
+        assert!(view.tick(1).is_none());
+    }
+
+    #[test]
+    fn echo_trails_fit_the_palette() {
+        let frame = CompositedFrame {
+            buffer: vec![1],
+            priority: vec![0],
+            width: 1,
+            height: 1,
+            zero_x: 0,
+            zero_y: 0,
+            duration: 1,
+        };
+        let palette = [0u16; 16];
+        let echo_palette = [0u16; 16];
+        let palettes = vec![&echo_palette[..]; 20];
+        let sprite = Sprite::new(vec![frame; 2], &palette);
+        let (trails, colors) = sprite.echo_trails((-1, 0), &palettes);
+        assert_eq!(colors.len(), 8 * 16);
+        // Oldest echo on the left, in the last row
+        assert_eq!(trails[0].width, 8);
+        assert_eq!(trails[0].buffer[0], 0x71);
+        assert_eq!(trails[0].buffer[7], 0x01);
+    }
+
+    #[test]
+    fn echo_trails_keep_every_palette_row() {
+        let frame = CompositedFrame {
+            buffer: vec![0x21, 0x43],
+            priority: vec![0, 0],
+            width: 2,
+            height: 1,
+            zero_x: 0,
+            zero_y: 0,
+            duration: 1,
+        };
+        let palette: Vec<u16> = (0..0x80).collect();
+        let echo_palette: Vec<u16> = (0x100..0x180).collect();
+        let palettes = vec![&echo_palette[..]; 20];
+        let sprite = Sprite::new(vec![frame; 2], &palette);
+        let (trails, colors) = sprite.echo_trails((-2, 0), &palettes);
+        // Two rows a layer leaves room for seven echoes
+        assert_eq!(colors.len(), 16 * 16);
+        assert_eq!(trails[0].width, 16);
+        // The newest frame's rows 2 and 4 are now 0 and 1
+        assert_eq!(trails[0].buffer[14], 0x01);
+        assert_eq!(trails[0].buffer[15], 0x13);
+        assert_eq!(colors[0x01], 0x21);
+        assert_eq!(colors[0x13], 0x43);
+        // The oldest echo's are 14 and 15, with its own colors
+        assert_eq!(trails[0].buffer[0], 0xE1);
+        assert_eq!(trails[0].buffer[1], 0xF3);
+        assert_eq!(colors[0xE1], 0x121);
+        assert_eq!(colors[0xF3], 0x143);
+    }
 }
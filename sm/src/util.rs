@@ -37,6 +37,78 @@ pub fn bgr555_rgbf32(bgr: &u16) -> RGBf32 {
     (r, g, b)
 }
 
+/// How 5 bit SNES channels get stretched out to 8 bits
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ColorConversion {
+    /// Multiply by 8, the way `bgr555_rgb888` does. White comes out as
+    /// (248, 248, 248).
+    Shift,
+    /// Copy the top bits into the bottom ones, so 0 stays 0 and 31 becomes
+    /// 255. What most emulators put in their screenshots, and the default.
+    #[default]
+    Replicate,
+    /// bsnes's gamma ramp, darker in the low end like a console on a CRT
+    Gamma,
+}
+
+static GAMMA_RAMP: [u8; 32] = [
+    0x00, 0x01, 0x03, 0x06, 0x0A, 0x0F, 0x15, 0x1C,
+    0x24, 0x2D, 0x37, 0x42, 0x4E, 0x5B, 0x69, 0x78,
+    0x88, 0x90, 0x98, 0xA0, 0xA8, 0xB0, 0xB8, 0xC0,
+    0xC8, 0xD0, 0xD8, 0xE0, 0xE8, 0xF0, 0xF8, 0xFF,
+];
+
+impl ColorConversion {
+    fn channel(&self, c: u16) -> u8 {
+        let c = (c & 0b11111) as u8;
+        match *self {
+            ColorConversion::Shift => c << 3,
+            ColorConversion::Replicate => (c << 3) | (c >> 2),
+            ColorConversion::Gamma => GAMMA_RAMP[c as usize],
+        }
+    }
+
+    // Closest 5 bit value that converts back to `c`. Both linear ones keep
+    // the original bits on top.
+    fn unchannel(&self, c: u8) -> u16 {
+        match *self {
+            ColorConversion::Shift | ColorConversion::Replicate => (c >> 3) as u16,
+            ColorConversion::Gamma => {
+                let distance = |n: &usize| (GAMMA_RAMP[*n] as i16 - c as i16).abs();
+                (0..GAMMA_RAMP.len()).min_by_key(distance).unwrap_or(0) as u16
+            },
+        }
+    }
+
+    pub fn rgb888(&self, bgr: &u16) -> RGBu8 {
+        (self.channel(*bgr), self.channel(bgr >> 5), self.channel(bgr >> 10))
+    }
+
+    pub fn rgbf32(&self, bgr: &u16) -> RGBf32 {
+        let (r, g, b) = self.rgb888(bgr);
+        (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+    }
+
+    /// The other way, for colors coming in from image editors
+    pub fn bgr555(&self, &(r, g, b): &RGBu8) -> u16 {
+        self.unchannel(r) | (self.unchannel(g) << 5) | (self.unchannel(b) << 10)
+    }
+}
+
+/// Frames index colors as `row * 16 + color`, so a single 16 color
+/// palette gets repeated for every OBJ palette row. Anything longer
+/// already has its rows laid out, and just gets padded out to 8 rows.
+pub fn palette_rows(palette: &[u16]) -> Vec<u16> {
+    let mut colors = palette.to_vec();
+    if colors.len() <= 16 {
+        colors.resize(16, 0);
+        colors = colors.iter().cycle().take(16 * 8).cloned().collect();
+    } else if colors.len() < 16 * 8 {
+        colors.resize(16 * 8, 0);
+    }
+    colors
+}
+
 #[allow(dead_code)]
 pub fn bgr555_rgb565(bgr: &u16) -> u16 {
     // Used by some oled screens
@@ -60,9 +132,7 @@ impl<T, U, V> Iterator for Zip3<T, U, V>
     fn next(&mut self) -> Option<Self::Item> {
         self.t.next().and_then(|t| {
             self.u.next().and_then(|u| {
-                self.v.next().and_then(|v| {
-                    Some((t, u, v))
-                })
+                self.v.next().map(|v| (t, u, v))
             })
         })
     }
@@ -94,3 +164,22 @@ pub fn zip3<T, U, V>(t: T, u: U, v: V) -> Zip3<T::IntoIter, U::IntoIter, V::Into
     where T: IntoIterator, U: IntoIterator, V: IntoIterator {
     Zip3 { t: t.into_iter(), u: u.into_iter(), v: v.into_iter() }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ColorConversion;
+
+    #[test]
+    fn conversions_reach_white() {
+        let white = 0x7FFF;
+        assert_eq!(ColorConversion::Shift.rgb888(&white), (248, 248, 248));
+        assert_eq!(ColorConversion::Replicate.rgb888(&white), (255, 255, 255));
+        assert_eq!(ColorConversion::Gamma.rgb888(&white), (255, 255, 255));
+        assert_eq!(ColorConversion::Replicate.rgb888(&0x0010), (0x84, 0, 0));
+        for &conversion in [ColorConversion::Shift, ColorConversion::Replicate, ColorConversion::Gamma].iter() {
+            for &color in [0x0000, 0x0421, 0x5294, 0x7FFF].iter() {
+                assert_eq!(conversion.bgr555(&conversion.rgb888(&color)), color);
+            }
+        }
+    }
+}
@@ -0,0 +1,271 @@
+// Samus's own projectiles. Bank $93 has a table of projectile data for every
+// beam combination, charged and not, and one for missiles, super missiles,
+// bombs and power bombs. Each entry is the damage followed by an instruction
+// list per direction. Those lists are a simpler format than the enemy ones:
+// 8 byte frames (timer, spritemap, x radius, y radius, trail frame) and only
+// two instructions.
+
+use snes::{Rom, PcAddress, SnesAddress};
+use std::{cmp, fmt};
+use byteorder::{ByteOrder, LittleEndian};
+use snes_bitplanes::{Bitplanes, Tile};
+use frame_map::FrameMap;
+use enemy::{common_sprite_tiles, Animation, Frame, Hitbox};
+use instruction_list::End;
+
+const WEAPON_BANK: u32 = 0x930000;
+const UNCHARGED_BEAMS: SnesAddress = SnesAddress(0x9383C1);
+const CHARGED_BEAMS: SnesAddress = SnesAddress(0x9383D9);
+const NON_BEAMS: SnesAddress = SnesAddress(0x9383F1);
+// Which entry of the NON_BEAMS table each one is
+const MISSILE_ENTRY: usize = 1;
+const SUPER_MISSILE_ENTRY: usize = 2;
+const POWER_BOMB_ENTRY: usize = 3;
+const BOMB_ENTRY: usize = 5;
+
+const DELETE: u16 = 0x8239;
+const GOTO: u16 = 0x8240;
+const FRAME_SIZE: usize = 8;
+const MAX_FRAMES: usize = 0x40;
+
+// Beam tiles get loaded over part of the common sprite tiles, tiles $30 to
+// $4F. Everything else draws straight from the common tiles.
+const BEAM_TILES_START: usize = 0x30;
+const BEAM_TILES_SIZE: usize = 0x400;
+const BEAM_GRAPHICS_POINTERS: SnesAddress = SnesAddress(0x90C3B1);
+const BEAM_PALETTE_POINTERS: SnesAddress = SnesAddress(0x90C3C9);
+const GRAPHICS_BANK: u32 = 0x9A0000;
+const PALETTE_BANK: u32 = 0x9B0000;
+// Second row of the common sprite palettes
+const COMMON_PALETTE: SnesAddress = SnesAddress(0x9A8120);
+
+/// In the order the game indexes them: wave, ice, spazer and plasma bits,
+/// minus spazer + plasma which can't be combined
+pub static BEAMS: [&str; 12] = [
+    "power", "wave", "ice", "ice_wave",
+    "spazer", "spazer_wave", "spazer_ice", "spazer_ice_wave",
+    "plasma", "plasma_wave", "plasma_ice", "plasma_ice_wave",
+];
+
+/// Up facing right, clockwise round to up facing left
+pub static DIRECTIONS: [&str; 10] = [
+    "up_right", "upright", "right", "downright", "down_right",
+    "down_left", "downleft", "left", "upleft", "up_left",
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Beam(usize),
+    ChargedBeam(usize),
+    Missile,
+    SuperMissile,
+    Bomb,
+    PowerBomb,
+}
+
+impl Kind {
+    fn table_entry(&self) -> PcAddress {
+        match *self {
+            Kind::Beam(n) => UNCHARGED_BEAMS.to_pc() + n * 2,
+            Kind::ChargedBeam(n) => CHARGED_BEAMS.to_pc() + n * 2,
+            Kind::Missile => NON_BEAMS.to_pc() + MISSILE_ENTRY * 2,
+            Kind::SuperMissile => NON_BEAMS.to_pc() + SUPER_MISSILE_ENTRY * 2,
+            Kind::PowerBomb => NON_BEAMS.to_pc() + POWER_BOMB_ENTRY * 2,
+            Kind::Bomb => NON_BEAMS.to_pc() + BOMB_ENTRY * 2,
+        }
+    }
+
+    /// Bombs just sit there, so they only have the one instruction list
+    fn is_directional(&self) -> bool {
+        !matches!(*self, Kind::Bomb | Kind::PowerBomb)
+    }
+
+    pub fn name(&self) -> String {
+        match *self {
+            Kind::Beam(n) => BEAMS[n].to_string(),
+            Kind::ChargedBeam(n) => format!("charged_{}", BEAMS[n]),
+            Kind::Missile => "missile".to_string(),
+            Kind::SuperMissile => "super_missile".to_string(),
+            Kind::Bomb => "bomb".to_string(),
+            Kind::PowerBomb => "power_bomb".to_string(),
+        }
+    }
+}
+
+/// Every beam combination, charged and uncharged, then the rest
+pub fn weapons<'a>(rom: &'a Rom) -> Vec<Weapon<'a>> {
+    let beams = (0..BEAMS.len()).map(Kind::Beam);
+    let charged = (0..BEAMS.len()).map(Kind::ChargedBeam);
+    let others = vec![Kind::Missile, Kind::SuperMissile, Kind::Bomb, Kind::PowerBomb];
+    beams.chain(charged).chain(others)
+        .map(|kind| Weapon::read_from_rom(rom, kind))
+        .collect()
+}
+
+pub struct Weapon<'a> {
+    kind: Kind,
+    address: SnesAddress,
+    damage: u16,
+    instruction_lists: Vec<u16>,
+    rom: &'a Rom<'a>,
+}
+
+impl<'a> Weapon<'a> {
+    pub fn read_from_rom(rom: &'a Rom, kind: Kind) -> Self {
+        let pointer = LittleEndian::read_u16(rom.read(kind.table_entry(), 2)) as u32;
+        let address = SnesAddress(WEAPON_BANK | pointer);
+        let num_lists = if kind.is_directional() { DIRECTIONS.len() } else { 1 };
+        let data = rom.read(address.to_pc(), 2 + num_lists * 2);
+        Weapon {
+            kind,
+            address,
+            damage: LittleEndian::read_u16(&data[0..2]),
+            instruction_lists: data[2..].chunks(2).map(LittleEndian::read_u16).collect(),
+            rom,
+        }
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn name(&self) -> String {
+        self.kind.name()
+    }
+
+    pub fn address(&self) -> SnesAddress {
+        self.address
+    }
+
+    pub fn damage(&self) -> u16 {
+        self.damage
+    }
+
+    /// One animation per direction, or just the one for bombs
+    pub fn animations(&self) -> Vec<Animation> {
+        self.instruction_lists.iter().enumerate()
+            .map(|(n, &pointer)| {
+                let name = if self.kind.is_directional() { DIRECTIONS[n] } else { "" };
+                self.animation_at(name, pointer)
+            })
+            .collect()
+    }
+
+    pub fn graphics(&self) -> Vec<Tile> {
+        let tiles = common_sprite_tiles(self.rom);
+        match self.kind {
+            Kind::Beam(n) | Kind::ChargedBeam(n) => {
+                let pointer = LittleEndian::read_u16(self.rom.read(BEAM_GRAPHICS_POINTERS.to_pc() + n * 2, 2)) as u32;
+                let beam = self.rom.read(SnesAddress(GRAPHICS_BANK | pointer).to_pc(), BEAM_TILES_SIZE);
+                load_beam_tiles(tiles, Bitplanes::new(beam).collect())
+            },
+            _ => tiles,
+        }
+    }
+
+    pub fn palette(&self) -> Vec<u16> {
+        let addr = match self.kind {
+            Kind::Beam(n) | Kind::ChargedBeam(n) => {
+                let pointer = LittleEndian::read_u16(self.rom.read(BEAM_PALETTE_POINTERS.to_pc() + n * 2, 2)) as u32;
+                SnesAddress(PALETTE_BANK | pointer)
+            },
+            _ => COMMON_PALETTE,
+        };
+        self.rom.read(addr.to_pc(), 32).chunks(2).map(LittleEndian::read_u16).collect()
+    }
+
+    fn animation_at(&self, name: &str, pointer: u16) -> Animation {
+        let address = SnesAddress(WEAPON_BANK | pointer as u32);
+        let mut pc = pointer;
+        let mut frames = Vec::new();
+        let mut visited: Vec<(u16, usize)> = Vec::new();
+        let end = loop {
+            if frames.len() >= MAX_FRAMES {
+                break End::StepLimit(pc);
+            }
+            visited.push((pc, frames.len()));
+            let data = self.rom.read(SnesAddress(WEAPON_BANK | pc as u32).to_pc(), FRAME_SIZE);
+            match LittleEndian::read_u16(&data[0..2]) {
+                DELETE => break End::Delete,
+                GOTO => {
+                    let target = LittleEndian::read_u16(&data[2..4]);
+                    break match visited.iter().find(|&&(a, _)| a == target) {
+                        Some(&(_, index)) => End::Loop(index),
+                        None => End::GoTo(target),
+                    };
+                },
+                op if op >= 0x8000 => break End::Unknown(op),
+                duration => {
+                    let spritemap = LittleEndian::read_u16(&data[2..4]) as u32;
+                    let (x_radius, y_radius) = (data[4] as i16, data[5] as i16);
+                    let parts = FrameMap::from_rom(self.rom, SnesAddress(WEAPON_BANK | spritemap), 0);
+                    let hitbox = Hitbox {
+                        left: -x_radius,
+                        top: -y_radius,
+                        right: x_radius,
+                        bottom: y_radius,
+                        touch: 0,
+                        shot: 0,
+                    };
+                    frames.push(Frame::new(parts, vec![hitbox], duration));
+                    pc = pc.wrapping_add(FRAME_SIZE as u16);
+                },
+            }
+        };
+        Animation {
+            name: name.to_string(),
+            address,
+            frames,
+            end,
+        }
+    }
+}
+
+// Only the beam's own slots get overwritten, the common tiles after them
+// stay put
+fn load_beam_tiles(mut tiles: Vec<Tile>, beam: Vec<Tile>) -> Vec<Tile> {
+    if tiles.len() < BEAM_TILES_START {
+        tiles.resize(BEAM_TILES_START, Tile([0; 64]));
+    }
+    let end = cmp::min(BEAM_TILES_START + beam.len(), tiles.len());
+    tiles.splice(BEAM_TILES_START..end, beam);
+    tiles
+}
+
+impl<'a> fmt::Debug for Weapon<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Weapon {{ name: {}, address: {:06X}, damage: {} }}", self.name(), self.address.0, self.damage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_beam_tiles, Kind, BEAMS, CHARGED_BEAMS, NON_BEAMS};
+    use snes::PcAddress;
+    use snes_bitplanes::Tile;
+
+    #[test]
+    fn table_entries() {
+        assert_eq!(Kind::Beam(0).table_entry(), PcAddress(0x983C1));
+        assert_eq!(Kind::Beam(3).table_entry(), PcAddress(0x983C7));
+        // Each beam table runs right up to the next one
+        assert_eq!(Kind::Beam(BEAMS.len() - 1).table_entry() + 2, CHARGED_BEAMS.to_pc());
+        assert_eq!(Kind::ChargedBeam(BEAMS.len() - 1).table_entry() + 2, NON_BEAMS.to_pc());
+        assert_eq!(Kind::Missile.table_entry(), PcAddress(0x983F3));
+        assert_eq!(Kind::SuperMissile.table_entry(), PcAddress(0x983F5));
+        assert_eq!(Kind::PowerBomb.table_entry(), PcAddress(0x983F7));
+        assert_eq!(Kind::Bomb.table_entry(), PcAddress(0x983FB));
+    }
+
+    #[test]
+    fn beam_tiles_leave_the_rest_alone() {
+        let common: Vec<_> = (0..0x80).map(|n| Tile([n as u8; 64])).collect();
+        let tiles = load_beam_tiles(common, vec![Tile([0xFF; 64]); 0x20]);
+        assert_eq!(tiles.len(), 0x80);
+        assert_eq!(tiles[0x2F].0[0], 0x2F);
+        assert_eq!(tiles[0x30].0[0], 0xFF);
+        assert_eq!(tiles[0x4F].0[0], 0xFF);
+        assert_eq!(tiles[0x50].0[0], 0x50);
+        assert_eq!(tiles[0x7F].0[0], 0x7F);
+    }
+}
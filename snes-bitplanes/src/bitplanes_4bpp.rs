@@ -75,7 +75,7 @@ impl<'a> Bitplanes<'a> {
     ///
     /// Panics if the length of the slice is not evenly divisible by 32.
     pub fn new(bytes: &'a [u8]) -> Bitplanes<'a> {
-        assert!(bytes.len() % 32 == 0, "Byte slice doesn't fit into 32-byte tiles");
+        assert!(bytes.len().is_multiple_of(32), "Byte slice doesn't fit into 32-byte tiles");
         Bitplanes {
             chunks: bytes.chunks(32),
         }
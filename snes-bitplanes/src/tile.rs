@@ -90,11 +90,11 @@ impl ops::Index<usize> for Tile {
 }
 
 impl Tile {
-    pub fn iter(&self) -> Iter<u8> {
+    pub fn iter(&self) -> Iter<'_, u8> {
         self.0.iter()
     }
 
-    pub fn chunks(&self, n: usize) -> Chunks<u8> {
+    pub fn chunks(&self, n: usize) -> Chunks<'_, u8> {
         self.0.chunks(n)
     }
 }
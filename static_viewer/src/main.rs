@@ -110,7 +110,7 @@ fn main() {
                 _ => {
                     get_input(b)
                     .map(|input| {
-                        current_input = input;
+                        current_input = current_input | input;
                         println!("{:?}", current_input);
                         samus.input(current_input)
                     })
@@ -134,6 +134,7 @@ fn main() {
         }
 
         if let Some(_) = event.update_args() {
+            samus.tick();
             let now = time::Instant::now();
             if now >= next_frame_time {
 
@@ -6,18 +6,23 @@ extern crate snes_bitplanes;
 extern crate sm;
 
 mod write_gif;
+mod write_png;
+mod write_ase;
 
 use sm::*;
 
-use snes::{Rom, PcAddress, SnesAddress};
-use enemy::DNA;
-use sprite::{Sprite, SpriteView};
+use snes::{Rom, SnesAddress};
+use enemy::{DNA, SpritemapFormat};
+use sprite::{Playback, Sprite, SpriteView};
+use export::{AnimationFormat, FrameRate};
 use write_gif::write_sprite_to_gif;
+use write_png::{write_animation_file, write_atlas, write_sprite_to_pngs};
+use write_ase::write_animations_to_ase;
 use byteorder::{ByteOrder, LittleEndian};
-use util::{bgr555_rgbf32, zip3};
-use std::{env, thread, time, process};
+use util::{zip3, ColorConversion};
+use std::{cmp, env, fs, process};
 
-use frame_map::FrameMap;
+use frame_map::{CompositedFrame, FrameMap, Rect};
 use snes_bitplanes::Tile;
 
 use piston_window::*;
@@ -28,7 +33,11 @@ const ROM: Rom = Rom(ROM_DATA);
 fn render_animation(sprite: Sprite) {
     let opengl = OpenGL::V3_2;
     let zoom = 4usize;
-    let (window_width, window_height) = (64, 64);
+    let (window_width, window_height) = (cmp::max(64, sprite.width() as usize), cmp::max(64, sprite.height() as usize));
+    // Line every frame up on the sprite's shared origin, centered in the window
+    let (zero_x, zero_y) = sprite.zero();
+    let margin_x = (window_width - sprite.width() as usize) / 2 + zero_x as usize;
+    let margin_y = (window_height - sprite.height() as usize) / 2 + zero_y as usize;
     let mut window: PistonWindow =WindowSettings::new("samus",
         [(window_width * zoom) as u32, (window_height * zoom) as u32])
             .exit_on_esc(true)
@@ -38,15 +47,47 @@ fn render_animation(sprite: Sprite) {
             .unwrap();
     let palette = sprite.palettef32();
     let mut spriteview = SpriteView::new(&sprite);
+    // Leftover fractions of a 60Hz tick between updates
+    let mut ticks = 0.0f64;
 
+    // Space pauses, F/R/P play forward, in reverse or ping-pong, and the
+    // arrow keys step through frames while paused
     while let Some(event) = window.next() {
+        if let Some(args) = event.update_args() {
+            ticks += args.dt * 60.0;
+            let whole = ticks.floor();
+            ticks -= whole;
+            spriteview.tick(whole as u32);
+        }
+
+        if let Some(Button::Keyboard(key)) = event.press_args() {
+            match key {
+                Key::Space => spriteview.toggle_pause(),
+                Key::F => spriteview.set_playback(Playback::Forward),
+                Key::R => spriteview.set_playback(Playback::Reverse),
+                Key::P => spriteview.set_playback(Playback::PingPong),
+                Key::Right => {
+                    spriteview.pause();
+                    spriteview.step_forward();
+                },
+                Key::Left => {
+                    spriteview.pause();
+                    spriteview.step_back();
+                },
+                _ => (),
+            }
+        }
+
+        let composite = match spriteview.current() {
+            Some(composite) => composite,
+            None => continue,
+        };
         window.draw_2d(&event, |context, graphics| {
             if let Some(_) = event.render_args() {
                 clear([0.0; 4], graphics);
 
-                let ref composite = spriteview.frame();
-                let offset_x = window_width / 2 - composite.zero_x as usize;
-                let offset_y = window_height / 2 - composite.zero_y as usize;
+                let offset_x = margin_x - composite.zero_x as usize;
+                let offset_y = margin_y - composite.zero_y as usize;
 
                 for (i, p) in composite.buffer.iter().enumerate() {
                     if *p == 0 {
@@ -61,15 +102,13 @@ fn render_animation(sprite: Sprite) {
                         graphics,
                     )
                 }
-                let duration = time::Duration::from_millis(composite.duration as u64 * 16);
-                thread::sleep(duration);
             }
         });
     }
 }
 
-fn render_tile_map(tiles: &[Tile], palette: &[u16]) {
-    let palette: Vec<_> = palette.iter().map(bgr555_rgbf32).collect();
+fn render_tile_map(tiles: &[Tile], palette: &[u16], conversion: ColorConversion) {
+    let palette: Vec<_> = palette.iter().map(|c| conversion.rgbf32(c)).collect();
     let opengl = OpenGL::V3_2;
     let zoom = 2usize;
     let mut window: PistonWindow = WindowSettings::new("sprite sheet",
@@ -103,15 +142,206 @@ fn render_tile_map(tiles: &[Tile], palette: &[u16]) {
     }
 }
 
+fn enemy_frames(creature: &DNA, animation: usize) -> Vec<enemy::Frame> {
+    let mut animations = creature.animations();
+    if animation < animations.len() {
+        animations.swap_remove(animation).frames
+    } else if animation == 0 {
+        creature.frames()
+    } else {
+        eprintln!("Enemy only has {} animations.", animations.len());
+        process::exit(1);
+    }
+}
+
+fn enemy_palette(creature: &DNA, catalog: &[DNA], choice: Option<&str>, conversion: ColorConversion) -> Vec<u16> {
+    if let Some(palette) = choice.and_then(|name| palette_file(name, conversion)) {
+        return palette;
+    }
+    let bytes = match choice {
+        None => creature.palette(),
+        Some("hurt") => enemy::hurt_palette(&ROM),
+        Some("frozen") => enemy::frozen_palette(&ROM),
+        Some(s) => {
+            let mut palettes = creature.alternative_palettes(catalog);
+            match usize::from_str_radix(s, 10) {
+                Ok(n) if n < palettes.len() => palettes.swap_remove(n).1,
+                _ => {
+                    eprintln!("Couldn't find palette {:?}, enemy has {} variants.", s, palettes.len());
+                    process::exit(1);
+                },
+            }
+        },
+    };
+    bytes.chunks(2).map(LittleEndian::read_u16).collect()
+}
+
+/// Colors from a palette file, if `name` looks like one
+fn palette_file(name: &str, conversion: ColorConversion) -> Option<Vec<u16>> {
+    palette::Format::guess(name, &[])?;
+    let data = fs::read(name).unwrap_or_else(|e| {
+        eprintln!("Couldn't read palette file {:?}: {}", name, e);
+        process::exit(1);
+    });
+    let format = palette::Format::guess(name, &data)?;
+    match palette::read(&data, format, conversion) {
+        Ok(palette) => Some(palette),
+        Err(e) => {
+            eprintln!("Couldn't load {:?} as a {:?} palette: {}", name, format, e);
+            process::exit(1);
+        },
+    }
+}
+
+fn samus_palette(name: &str, conversion: ColorConversion) -> Vec<u16> {
+    if let Some(palette) = palette_file(name, conversion) {
+        return palette;
+    }
+    let mut palettes = samus::palettes(&ROM);
+    match palettes.iter().position(|&(ref n, _)| n == name) {
+        Some(index) => palettes.swap_remove(index).1,
+        None => {
+            let names: Vec<_> = palettes.iter().map(|&(ref n, _)| n.as_str()).collect();
+            eprintln!("Couldn't find Samus palette {:?}. Try one of {}", name, names.join(", "));
+            process::exit(1);
+        },
+    }
+}
+
+const NUM_ECHOES: usize = 3;
+const ECHO_SPACING: i16 = 12;
+
+// Each frame gets the frames before it trailing behind Samus, the way they
+// would if she were running at full speed
+fn samus_echoes(state: usize, frames: Vec<CompositedFrame>, palette: Vec<u16>, palette_name: &str) -> (Vec<CompositedFrame>, Vec<u16>) {
+    // Custom palettes get the power suit's echoes
+    let suit = match palette::Format::guess(palette_name, &[]) {
+        Some(_) => "power",
+        None => palette_name.split('_').next().unwrap_or("power"),
+    };
+    let echo_palettes = samus::echo_palettes(&ROM, suit, "speed_booster");
+    if echo_palettes.is_empty() {
+        eprintln!("Couldn't find speed booster palettes for {:?}.", suit);
+        process::exit(1);
+    }
+    let behind = match samus::pose_definition(&ROM, state).direction {
+        samus::Direction::Left => ECHO_SPACING,
+        _ => -ECHO_SPACING,
+    };
+    let palettes: Vec<&[u16]> = (0..NUM_ECHOES).map(|k| echo_palettes[k % echo_palettes.len()].as_slice()).collect();
+    let sprite = Sprite::new(frames, &palette);
+    sprite.echo_trails((behind, 0), &palettes)
+}
+
+// Brightest color in most enemy palettes
+const HITBOX_COLOR: u8 = 0x0F;
+
+/// Composites every frame of a pose, along with the tiles each frame uses
+fn samus_frames(state: usize, cannon: bool) -> (Vec<CompositedFrame>, Vec<Vec<Tile>>) {
+    let durations = samus::lookup_frame_sequence(&ROM, state).0;
+    let mut tile_maps = samus::tilemaps(&ROM, state, durations.len());
+    let mut tile_sets = samus::graphics(&ROM, state, durations.len());
+    if cannon {
+        samus::add_cannon(&ROM, state, &mut tile_maps, &mut tile_sets);
+    }
+    let frames = zip3(tile_maps, &tile_sets, durations)
+        .enumerate()
+        .map(|(n, (tm, ts, ds))| {
+            let (frame, skipped) = FrameMap::composite_with_diagnostics(&tm, &ts, &[], 0, *ds as u16, 0);
+            for part in skipped {
+                eprintln!("Pose {:02X} frame {}: skipped {}", state, n, part);
+            }
+            frame
+        }).collect();
+    (frames, tile_sets)
+}
+
+/// Writes every animation to one atlas or aseprite file. Atlases get the
+/// hitboxes drawn in like everything else, aseprite files put them on a layer.
+fn write_every_animation(name: &str, animations: Vec<(String, &[enemy::Frame])>, tiles: &[Tile], palette: &[u16], action: &Action) {
+    match action.format {
+        Format::Atlas => {
+            let animations: Vec<_> = animations.into_iter()
+                .map(|(tag, frames)| (tag, composite_enemy_frames(frames, tiles, action.hitboxes)))
+                .collect();
+            write_atlas(&format!("{}_atlas", name), &animations, palette, action.conversion).expect("couldn't write atlas");
+        },
+        Format::Aseprite => {
+            let hitboxes = if action.hitboxes {
+                Some(animations.iter()
+                    .flat_map(|&(_, frames)| frames.iter().map(|f| f.hitboxes().iter().map(enemy::Hitbox::rect).collect()))
+                    .collect())
+            } else {
+                None
+            };
+            let animations: Vec<_> = animations.into_iter()
+                .map(|(tag, frames)| (tag, composite_enemy_frames(frames, tiles, false)))
+                .collect();
+            write_animations_to_ase(name, animations, hitboxes, palette, action.conversion).expect("couldn't write aseprite file");
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// Composites enemy, projectile or weapon frames, reporting any parts that
+/// didn't make it like `samus_frames` does
+fn composite_enemy_frames(frames: &[enemy::Frame], tiles: &[Tile], hitboxes: bool) -> Vec<CompositedFrame> {
+    let color = if hitboxes { Some(HITBOX_COLOR) } else { None };
+    frames.iter()
+        .enumerate()
+        .map(|(n, f)| {
+            let (frame, skipped) = f.composited_with_diagnostics(tiles, color);
+            for part in skipped {
+                eprintln!("Frame {}: skipped {}", n, part);
+            }
+            frame
+        }).collect()
+}
+
+// Tab separated, one enemy per line. Weapon columns are damage multipliers,
+// marked with a * when that weapon can freeze the enemy.
+fn print_catalog() {
+    print!("address\tname\thealth\tdamage");
+    for weapon in enemy::WEAPONS.iter() {
+        print!("\t{:?}", weapon);
+    }
+    println!("\tsmall_energy\tlarge_energy\tmissile\tnothing\tsuper_missile\tpower_bomb");
+
+    for creature in enemy::catalog(&ROM) {
+        print!("{:06X}\t{}\t{}\t{}",
+            creature.address().0, creature.name().unwrap_or_default(), creature.health(), creature.damage());
+        for (_weapon, vulnerability) in creature.vulnerabilities() {
+            print!("\t{}{}", vulnerability.damage_multiplier(), if vulnerability.freezable { "*" } else { "" });
+        }
+        match creature.drop_chances() {
+            Some(d) => println!("\t{}\t{}\t{}\t{}\t{}\t{}",
+                d.small_energy, d.large_energy, d.missile, d.nothing, d.super_missile, d.power_bomb),
+            None => println!("\t\t\t\t\t\t"),
+        }
+    }
+}
+
 struct Action {
     frames: usize,
+    animation: usize,
+    hitboxes: bool,
+    cannon: bool,
+    echoes: bool,
+    palette: Option<String>,
+    owner: Option<u32>,
     address: Option<u32>,
     subject: Option<Subject>,
     format: Format,
+    frame_rate: FrameRate,
+    conversion: ColorConversion,
 }
 
 enum Subject {
+    Catalog,
+    Projectile,
+    Weapon,
     Enemy,
+    Boss,
     Samus,
 }
 
@@ -119,6 +349,21 @@ enum Format {
     Spritesheet,
     Animate,
     Gif,
+    Png,
+    Atlas,
+    Aseprite,
+    Animation(AnimationFormat),
+}
+
+impl Format {
+    /// Formats that get every animation of the subject in one file, instead
+    /// of just the one picked with @n
+    fn has_every_animation(&self) -> bool {
+        match *self {
+            Format::Atlas | Format::Aseprite => true,
+            _ => false,
+        }
+    }
 }
 
 static HINT_STRING: &'static str =
@@ -131,14 +376,40 @@ static FLAG_STRING: &'static str =
     "Try:\n\
     -s (spritesheet)\n\
     -a (animate)\n\
-    -g (gif)";
+    -g (gif)\n\
+    -p (png)\n\
+    -t (texture atlas)\n\
+    -ase (aseprite)\n\
+    -apng (animated png)\n\
+    -webp (animated webp, with the webp feature)\n\
+    -b (hitboxes)\n\
+    -pal (50Hz timing)\n\
+    -shift, -gamma (color conversion)\n\
+    -c (arm cannon)\n\
+    -e (speed echoes)";
 
 static HELP_STRING: &'static str =
-    "[-s | -a | -g] <addr>\n\
+    "[-s | -a | -g | -p | -t | -ase | -apng | -webp] [-pal] [-shift | -gamma] [-b] [-c] [-e] <samus | enemy | boss> <addr> | samus <-t | -ase> | projectile [<addr> ^<enemy addr>] | weapon [<n>] | catalog\n\
     -s = spritesheet, default\n\
     -a = animate\n\
     -g = gif\n\
-    addr = SNES address in hex";
+    -p = png frames, sheet and tiles\n\
+    -t = texture atlas png + json, every animation (or every Samus pose) packed together\n\
+    -ase = aseprite file, every animation (or every Samus pose) tagged, with origin and hitbox layers\n\
+    -apng = animated png, frame delays exact to the tick\n\
+    -webp = animated lossless webp, if built with the webp feature\n\
+    -pal = time -apng and -webp animations for a 50Hz PAL console\n\
+    -shift = colors times 8 (white is 248), instead of filling the low bits in (white is 255)\n\
+    -gamma = colors through a CRT-ish gamma curve instead\n\
+    -b = outline enemy hitboxes, or give them their own aseprite layer\n\
+    -c = draw Samus's arm cannon\n\
+    -e = draw Samus's speed booster echoes behind her\n\
+    addr = SNES address in hex\n\
+    @n = enemy animation number or weapon direction, default 0\n\
+    ~n = enemy palette variant number, or ~hurt, ~frozen\n\
+    ~name = Samus palette, e.g. ~varia or ~gravity_speed_booster_2\n\
+    ~file = palette file instead: .pal (JASC or YY-CHR), .gpl, .act, or .cgram/.bin (raw CGRAM)\n\
+    ^addr = enemy whose tiles and palette a projectile uses";
 
 fn main() {
     use Subject::*;
@@ -155,12 +426,38 @@ fn main() {
             subject: None,
             address: None,
             frames: 1,
+            animation: 0,
+            hitboxes: false,
+            cannon: false,
+            echoes: false,
+            palette: None,
+            owner: None,
+            frame_rate: FrameRate::Ntsc,
+            conversion: ColorConversion::default(),
         }, |mut action, arg| {
-            if arg.starts_with("-") {
+            if arg == "-b" {
+                action.hitboxes = true;
+            } else if arg == "-pal" {
+                action.frame_rate = FrameRate::Pal;
+            } else if arg == "-shift" {
+                action.conversion = ColorConversion::Shift;
+            } else if arg == "-gamma" {
+                action.conversion = ColorConversion::Gamma;
+            } else if arg == "-c" {
+                action.cannon = true;
+            } else if arg == "-e" {
+                action.echoes = true;
+            } else if arg.starts_with("-") {
                 action.format = match arg.as_str() {
                     "-s" => Spritesheet,
                     "-a" => Animate,
                     "-g" => Gif,
+                    "-p" => Png,
+                    "-t" => Atlas,
+                    "-ase" => Aseprite,
+                    "-apng" => Animation(AnimationFormat::Apng),
+                    #[cfg(feature = "webp")]
+                    "-webp" => Animation(AnimationFormat::WebP),
                     s @ _ => {
                         eprintln!("Unknown flag {:?}. {}", s, FLAG_STRING);
                         process::exit(1)
@@ -174,11 +471,37 @@ fn main() {
                     eprintln!("Couldn't parse number of frames {:?}.", s);
                     process::exit(1);
                 }
+            } else if arg.starts_with("^") {
+                let s: String = arg.chars().skip(1).collect();
+                if let Ok(addr) = u32::from_str_radix(&s, 16) {
+                    action.owner = Some(addr);
+                } else {
+                    eprintln!("Couldn't parse enemy address {:?} as hex. {}", s, HINT_STRING);
+                    process::exit(1)
+                }
+            } else if arg.starts_with("~") {
+                action.palette = Some(arg.chars().skip(1).collect());
+            } else if arg.starts_with("@") {
+                let s: String = arg.chars().skip(1).collect();
+                if let Ok(animation) = usize::from_str_radix(&s, 10) {
+                    action.animation = animation;
+                } else {
+                    eprintln!("Couldn't parse animation number {:?}.", s);
+                    process::exit(1);
+                }
             } else {
                 if arg == "samus" {
                     action.subject = Some(Samus);
+                } else if arg == "catalog" {
+                    action.subject = Some(Catalog);
+                } else if arg == "projectile" {
+                    action.subject = Some(Projectile);
+                } else if arg == "weapon" {
+                    action.subject = Some(Weapon);
                 } else if arg == "enemy" {
                     action.subject = Some(Enemy);
+                } else if arg == "boss" {
+                    action.subject = Some(Boss);
                 } else if let Ok(addr) = u32::from_str_radix(&arg, 16) {
                     action.address = Some(addr);
                 } else {
@@ -190,63 +513,204 @@ fn main() {
         });
 
     match (action.subject, action.address) {
+        (Some(Catalog), _) => print_catalog(),
+        (Some(Projectile), None) => {
+            for projectile in projectile::catalog(&ROM) {
+                println!("{:?}", projectile);
+            }
+        },
+        (Some(Projectile), Some(addr)) => {
+            let addr = if addr == (addr & 0xFFFF) {
+                0x860000 | addr
+            } else {
+                addr
+            };
+            let owner = match action.owner {
+                Some(owner) if owner == (owner & 0xFFFF) => 0xA00000 | owner,
+                Some(owner) => owner,
+                None => {
+                    eprintln!("Projectiles borrow the tiles of the enemy firing them. Pass its address as ^addr.");
+                    process::exit(1);
+                },
+            };
+            let creature = DNA::read_from_rom(&ROM, SnesAddress(owner));
+            let catalog = enemy::catalog(&ROM);
+            let palette = enemy_palette(&creature, &catalog, action.palette.as_ref().map(String::as_str), action.conversion);
+            // Projectile spritemaps point into VRAM, not the enemy's own tiles
+            let tiles = creature.vram_graphics();
+            let mut animations = projectile::Projectile::read_from_rom(&ROM, SnesAddress(addr)).animations();
+            if action.format.has_every_animation() {
+                let named: Vec<_> = animations.iter().map(|a| (a.name.clone(), a.frames.as_slice())).collect();
+                write_every_animation(&format!("projectile_{:06X}", addr), named, &tiles, &palette, &action);
+                return;
+            }
+            if action.animation >= animations.len() {
+                eprintln!("Projectile only has {} animations.", animations.len());
+                process::exit(1);
+            }
+            let frames = composite_enemy_frames(&animations.swap_remove(action.animation).frames, &tiles, action.hitboxes);
+            let sprite = Sprite::new(frames, &palette).with_color_conversion(action.conversion);
+            match action.format {
+                Spritesheet => render_tile_map(&tiles, &palette, action.conversion),
+                Animate => render_animation(sprite),
+                Gif => write_sprite_to_gif(&format!("projectile_{:06X}", addr), &sprite).expect("YOUR GIF DIED MISSION FAILED"),
+                Png => write_sprite_to_pngs(&format!("projectile_{:06X}", addr), &sprite, &tiles).expect("couldn't write pngs"),
+                Animation(format) => write_animation_file(&format!("projectile_{:06X}", addr), &sprite, format, action.frame_rate).expect("couldn't write animation"),
+                Atlas | Aseprite => unreachable!(),
+            }
+        },
+        (Some(Weapon), None) => {
+            for (n, weapon) in weapons::weapons(&ROM).iter().enumerate() {
+                println!("{:X}\t{:?}", n, weapon);
+            }
+        },
+        (Some(Weapon), Some(n)) => {
+            let mut weapons = weapons::weapons(&ROM);
+            if n as usize >= weapons.len() {
+                eprintln!("There are only {} weapons. Run `weapon` to list them.", weapons.len());
+                process::exit(1);
+            }
+            let weapon = weapons.swap_remove(n as usize);
+            let tiles = weapon.graphics();
+            let palette = action.palette.as_ref()
+                .and_then(|name| palette_file(name, action.conversion))
+                .unwrap_or_else(|| weapon.palette());
+            let mut animations = weapon.animations();
+            if action.format.has_every_animation() {
+                let named: Vec<_> = animations.iter()
+                    .map(|a| {
+                        // Bombs don't have directions to name their one animation after
+                        let name = if a.name.is_empty() { weapon.name() } else { a.name.clone() };
+                        (name, a.frames.as_slice())
+                    })
+                    .collect();
+                write_every_animation(&weapon.name(), named, &tiles, &palette, &action);
+                return;
+            }
+            if action.animation >= animations.len() {
+                eprintln!("{} only has {} directions.", weapon.name(), animations.len());
+                process::exit(1);
+            }
+            let frames = composite_enemy_frames(&animations.swap_remove(action.animation).frames, &tiles, action.hitboxes);
+            let sprite = Sprite::new(frames, &palette).with_color_conversion(action.conversion);
+            match action.format {
+                Spritesheet => render_tile_map(&tiles, &palette, action.conversion),
+                Animate => render_animation(sprite),
+                Gif => write_sprite_to_gif(&weapon.name(), &sprite).expect("gif machine broke"),
+                Png => write_sprite_to_pngs(&weapon.name(), &sprite, &tiles).expect("couldn't write pngs"),
+                Animation(format) => write_animation_file(&weapon.name(), &sprite, format, action.frame_rate).expect("couldn't write animation"),
+                Atlas | Aseprite => unreachable!(),
+            }
+        },
+        (Some(Samus), None) => {
+            match action.format {
+                Atlas | Aseprite => {
+                    let palette = samus_palette(action.palette.as_ref().map(String::as_str).unwrap_or("power"), action.conversion);
+                    let poses = samus::poses(&ROM);
+                    let hitboxes: Vec<Vec<Rect>> = poses.iter()
+                        .flat_map(|pose| {
+                            let frames = samus::lookup_frame_sequence(&ROM, pose.state).0.len();
+                            vec![vec![pose.definition.hitbox()]; frames]
+                        })
+                        .collect();
+                    let poses: Vec<_> = poses.into_iter()
+                        .map(|pose| (pose.name, samus_frames(pose.state, action.cannon).0))
+                        .collect();
+                    if let Atlas = action.format {
+                        write_atlas("samus_atlas", &poses, &palette, action.conversion).expect("couldn't write atlas");
+                    } else {
+                        let hitboxes = if action.hitboxes { Some(hitboxes) } else { None };
+                        write_animations_to_ase("samus", poses, hitboxes, &palette, action.conversion).expect("couldn't write aseprite file");
+                    }
+                },
+                _ => {
+                    eprintln!("Pass a pose number, or -t or -ase to export every pose at once");
+                    process::exit(1);
+                },
+            }
+        },
         (Some(Samus), Some(addr)) => {
-            let sequence = samus::lookup_frame_sequence(&ROM, addr as usize);
-            let durations = sequence.0;
-            let tile_maps = samus::tilemaps(&ROM, addr as usize, durations.len());
-            let tile_sets = samus::graphics(&ROM, addr as usize, durations.len());
-            let frames: Vec<_> = zip3(tile_maps, &tile_sets, durations)
-                .map(|(tm, ts, ds)| FrameMap::composite(&tm, &ts, *ds as u16, 0)).collect();
-            let p = PcAddress(0xD9400); // lol trolled, not a snes address. There goes 1 day... :/
-            let palette: Vec<_> = ROM.read(p, 32)
-                .chunks(2)
-                .map(LittleEndian::read_u16)
-                .collect();
-            let sprite = Sprite::new(frames, &palette);
+            let (frames, tile_sets) = samus_frames(addr as usize, action.cannon);
+            let palette_name = action.palette.as_ref().map(String::as_str).unwrap_or("power");
+            let palette = samus_palette(palette_name, action.conversion);
+            let (frames, palette) = if action.echoes {
+                samus_echoes(addr as usize, frames, palette, palette_name)
+            } else {
+                (frames, palette)
+            };
+            let sprite = Sprite::new(frames, &palette).with_color_conversion(action.conversion);
+            let tiles = tile_sets.iter().fold(vec![], |mut acc, tiles| {
+                acc.extend_from_slice(&tiles);
+                acc
+            });
             match action.format {
                 Animate => render_animation(sprite),
-                Gif => write_sprite_to_gif(
-                        "samus",
-                        sprite.frames(),
-                        &sprite.palette888()
-                    ).expect("argh!"),
-                Spritesheet => {
-                    let tiles = tile_sets.iter().fold(vec![], |mut acc, tiles| {
-                        acc.extend_from_slice(&tiles);
-                        acc
-                    });
-                    render_tile_map(&tiles, &palette);
+                Gif => write_sprite_to_gif("samus", &sprite).expect("argh!"),
+                Png => write_sprite_to_pngs(&format!("samus_{:02X}", addr), &sprite, &tiles).expect("couldn't write pngs"),
+                Animation(format) => write_animation_file(&format!("samus_{:02X}", addr), &sprite, format, action.frame_rate).expect("couldn't write animation"),
+                Atlas => write_atlas(&format!("samus_{:02X}_atlas", addr), &[(format!("{:02X}", addr), sprite.frames().to_vec())], &palette, action.conversion).expect("couldn't write atlas"),
+                Aseprite => {
+                    let hitbox = samus::pose_definition(&ROM, addr as usize).hitbox();
+                    let hitboxes = if action.hitboxes { Some(vec![vec![hitbox]; sprite.frames().len()]) } else { None };
+                    let poses = vec![(format!("{:02X}", addr), sprite.frames().to_vec())];
+                    write_animations_to_ase(&format!("samus_{:02X}", addr), poses, hitboxes, &palette, action.conversion).expect("couldn't write aseprite file");
                 },
+                Spritesheet => render_tile_map(&tiles, &palette, action.conversion),
             };
         },
-        (Some(Enemy), Some(addr)) => {
+        (Some(subject), Some(addr)) => {
             let addr = if addr == (addr & 0xFFFF) {
                 0xA00000 | addr
             } else {
                 addr
             };
-            let creature = DNA::read_from_rom(&ROM, SnesAddress(addr));
-            let palette: Vec<_> = creature.palette().chunks(2)
-                .map(LittleEndian::read_u16).collect();
+            let format = match subject {
+                Boss => SpritemapFormat::Extended,
+                _ => SpritemapFormat::Normal,
+            };
+            let creature = DNA::read_from_rom(&ROM, SnesAddress(addr)).with_spritemap_format(format);
+            let catalog = enemy::catalog(&ROM);
+            let palette = enemy_palette(&creature, &catalog, action.palette.as_ref().map(String::as_str), action.conversion);
             let tiles = creature.graphics();
+            let vram = creature.vram_graphics();
 
             match action.format {
                 Spritesheet => {
-                    render_tile_map(&tiles, &palette);
+                    for (n, animation) in creature.animations().iter().enumerate() {
+                        println!("@{} {:?}", n, animation);
+                    }
+                    for (n, &(ref name, _)) in creature.alternative_palettes(&catalog).iter().enumerate() {
+                        println!("~{} {}", n, name);
+                    }
+                    render_tile_map(&tiles, &palette, action.conversion);
                 },
                 Animate => {
-                    let frames: Vec<_> = creature.frames().iter().map(|f| f.composited(&tiles)).collect();
-                    let sprite = Sprite::new(frames, &palette);
+                    let frames = composite_enemy_frames(&enemy_frames(&creature, action.animation), &vram, action.hitboxes);
+                    let sprite = Sprite::new(frames, &palette).with_color_conversion(action.conversion);
                     render_animation(sprite);
                 },
                 Gif => {
-                    let frames: Vec<_> = creature.frames().iter().map(|f| f.composited(&tiles)).collect();
-                    let sprite = Sprite::new(frames, &palette);
-                    write_sprite_to_gif(
-                        &creature.name().unwrap_or("enemy".to_string()),
-                        sprite.frames(),
-                        &sprite.palette888()
-                    ).expect("YOUR GIF DIED MISSION FAILED");
+                    let frames = composite_enemy_frames(&enemy_frames(&creature, action.animation), &vram, action.hitboxes);
+                    let sprite = Sprite::new(frames, &palette).with_color_conversion(action.conversion);
+                    write_sprite_to_gif(&creature.name().unwrap_or("enemy".to_string()), &sprite).expect("YOUR GIF DIED MISSION FAILED");
+                },
+                Png => {
+                    let frames = composite_enemy_frames(&enemy_frames(&creature, action.animation), &vram, action.hitboxes);
+                    let sprite = Sprite::new(frames, &palette).with_color_conversion(action.conversion);
+                    write_sprite_to_pngs(&creature.name().unwrap_or("enemy".to_string()), &sprite, &tiles).expect("couldn't write pngs");
+                },
+                Animation(format) => {
+                    let frames = composite_enemy_frames(&enemy_frames(&creature, action.animation), &vram, action.hitboxes);
+                    let sprite = Sprite::new(frames, &palette).with_color_conversion(action.conversion);
+                    write_animation_file(&creature.name().unwrap_or("enemy".to_string()), &sprite, format, action.frame_rate).expect("couldn't write animation");
+                },
+                Atlas | Aseprite => {
+                    let animations = creature.animations();
+                    let named: Vec<_> = animations.iter()
+                        .enumerate()
+                        .map(|(n, a)| (format!("{} {}", n, a.name), a.frames.as_slice()))
+                        .collect();
+                    write_every_animation(&creature.name().unwrap_or("enemy".to_string()), named, &vram, &palette, &action);
                 },
             }
         },
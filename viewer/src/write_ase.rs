@@ -0,0 +1,24 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use sm::aseprite;
+use sm::atlas::Tag;
+use sm::frame_map::{CompositedFrame, Rect};
+use sm::sprite::Sprite;
+use sm::util::ColorConversion;
+
+/// `name.ase` with every animation one after the other, each one tagged, and
+/// layers for the origin and (if there are any) the hitboxes of every frame
+pub fn write_animations_to_ase(name: &str, animations: Vec<(String, Vec<CompositedFrame>)>, hitboxes: Option<Vec<Vec<Rect>>>, palette: &[u16], conversion: ColorConversion) -> Result<(), io::Error> {
+    let mut tags = Vec::new();
+    let mut frames = Vec::new();
+    for (tag, animation) in animations {
+        if animation.is_empty() {
+            continue;
+        }
+        tags.push(Tag { name: tag, from: frames.len(), to: frames.len() + animation.len() - 1 });
+        frames.extend(animation);
+    }
+    let sprite = Sprite::new(frames, palette).with_color_conversion(conversion);
+    let file = BufWriter::new(File::create(format!("{}.ase", name))?);
+    aseprite::write_aseprite(file, &sprite, &tags, hitboxes.as_ref().map(Vec::as_slice), true)
+}
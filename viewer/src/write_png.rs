@@ -0,0 +1,45 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use sm::{atlas, export};
+use sm::export::{AnimationFormat, FrameRate};
+use sm::frame_map::CompositedFrame;
+use sm::sprite::Sprite;
+use sm::util::ColorConversion;
+use snes_bitplanes::Tile;
+
+fn create(name: &str) -> io::Result<BufWriter<File>> {
+    Ok(BufWriter::new(File::create(name)?))
+}
+
+/// `name.png` has every frame on one sheet, `name_tiles.png` the raw tiles,
+/// and `name_0.png`, `name_1.png`... each frame on its own
+pub fn write_sprite_to_pngs(name: &str, sprite: &Sprite, tiles: &[Tile]) -> Result<(), io::Error> {
+    export::sprite_sheet_png(create(&format!("{}.png", name))?, sprite)?;
+    export::tile_sheet_png(create(&format!("{}_tiles.png", name))?, tiles, sprite.palette(), sprite.color_conversion())?;
+    for (n, frame) in sprite.frames().iter().enumerate() {
+        export::frame_png(create(&format!("{}_{}.png", name, n))?, frame, sprite.palette(), sprite.color_conversion())?;
+    }
+    Ok(())
+}
+
+/// `name.apng` or `name.webp`
+pub fn write_animation_file(name: &str, sprite: &Sprite, format: AnimationFormat, rate: FrameRate) -> Result<(), io::Error> {
+    let extension = match format {
+        AnimationFormat::Apng => "apng",
+        #[cfg(feature = "webp")]
+        AnimationFormat::WebP => "webp",
+    };
+    export::write_animation(create(&format!("{}.{}", name, extension))?, sprite, format, rate)
+}
+
+/// `name.png` with every animation's frames packed together, and `name.json`
+/// describing where they went
+pub fn write_atlas(name: &str, animations: &[(String, Vec<CompositedFrame>)], palette: &[u16], conversion: ColorConversion) -> Result<(), io::Error> {
+    let animations: Vec<(&str, &[CompositedFrame])> = animations.iter()
+        .map(|&(ref name, ref frames)| (name.as_str(), frames.as_slice()))
+        .collect();
+    let atlas = atlas::pack(&animations)?;
+    let image = format!("{}.png", name);
+    atlas.write_png(create(&image)?, palette, conversion)?;
+    create(&format!("{}.json", name))?.write_all(atlas.to_json(&image).as_bytes())
+}
@@ -90,7 +90,7 @@ fn decode_input(key: u8) -> Option<ControllerInput> {
 #[no_mangle]
 pub extern fn input(state_ptr: *mut StateMachine<'static>, key: u8) -> bool {
     let mut state = unsafe { Box::from_raw(state_ptr) };
-    let ret = if let Some(i) = decode_input(key) {
+    let ret = if let Some(i) = decode_input(key).map(|i| state.current_input() | i) {
         state.input(i)
     } else {
         false
@@ -111,6 +111,13 @@ pub extern fn input_end(state_ptr: *mut StateMachine<'static>, key: u8) -> bool
     ret
 }
 
+#[no_mangle]
+pub extern fn tick(state_ptr: *mut StateMachine<'static>) {
+    let mut state = unsafe { Box::from_raw(state_ptr) };
+    state.tick();
+    mem::forget(state);
+}
+
 #[no_mangle]
 pub extern fn fall(state_ptr: *mut StateMachine<'static>) -> bool {
     let mut state = unsafe { Box::from_raw(state_ptr) };